@@ -0,0 +1,53 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    process,
+};
+
+// the library uses the same compiler for hot reloading, not everything in it is needed here
+#[allow(dead_code)]
+#[path = "src/base/shader/compile.rs"]
+mod compile;
+
+// compiles every shader in src/shaders to SPIR-V and writes them out as Bytecode constants
+// in $OUT_DIR/shaders.rs, which base::shader includes
+fn main() {
+    let shader_dir = Path::new("src").join("shaders");
+    println!("cargo:rerun-if-changed={}", shader_dir.display());
+
+    let mut paths = fs::read_dir(&shader_dir)
+        .unwrap_or_else(|e| panic!("Failed to read {}: {}", shader_dir.display(), e))
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| compile::is_shader(p))
+        .collect::<Vec<_>>();
+    paths.sort();
+
+    let mut generated = String::from("// generated by build.rs from src/shaders, do not edit\n\n");
+    let mut failed = false;
+
+    for path in &paths {
+        println!("cargo:rerun-if-changed={}", path.display());
+
+        match compile::compile_file(path) {
+            Ok(words) => {
+                let file_name = path.file_name().unwrap().to_string_lossy();
+                generated.push_str(&format!(
+                    "pub const {}: Bytecode = Bytecode::from_static(&{:?});\n",
+                    compile::constant_name(&file_name),
+                    words,
+                ));
+            },
+            Err(e) => {
+                eprintln!("{}", e);
+                failed = true;
+            },
+        }
+    }
+
+    if failed {
+        process::exit(1);
+    }
+
+    let out_dir = PathBuf::from(env::var_os("OUT_DIR").unwrap());
+    fs::write(out_dir.join("shaders.rs"), generated).unwrap();
+}
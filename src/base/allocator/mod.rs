@@ -0,0 +1,648 @@
+use std::{
+    cell::RefCell,
+    fmt, ptr,
+    rc::{Rc, Weak},
+};
+
+use ash::{vk, Device, Instance};
+
+use super::owned::Owned;
+
+use anyhow::{anyhow, Context, Result};
+
+use log::*;
+
+// size of the blocks sub-allocated from, smaller heaps get proportionally smaller blocks
+pub const DEFAULT_BLOCK_SIZE: vk::DeviceSize = 64 * 1024 * 1024;
+
+// what a resource needs from its memory. required flags must all be present, memory types
+// with more of the preferred flags are tried first
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryUsage {
+    pub required: vk::MemoryPropertyFlags,
+    pub preferred: vk::MemoryPropertyFlags,
+}
+
+impl MemoryUsage {
+    // render targets, textures, vertex data after upload
+    pub const GPU_ONLY: Self = Self {
+        required: vk::MemoryPropertyFlags::DEVICE_LOCAL,
+        preferred: vk::MemoryPropertyFlags::empty(),
+    };
+
+    // staging buffers
+    pub const CPU_ONLY: Self = Self {
+        required: vk::MemoryPropertyFlags::from_raw(
+            vk::MemoryPropertyFlags::HOST_VISIBLE.as_raw() | vk::MemoryPropertyFlags::HOST_COHERENT.as_raw()
+        ),
+        preferred: vk::MemoryPropertyFlags::empty(),
+    };
+
+    // data rewritten by the cpu every frame, like uniforms
+    pub const CPU_TO_GPU: Self = Self {
+        required: vk::MemoryPropertyFlags::from_raw(
+            vk::MemoryPropertyFlags::HOST_VISIBLE.as_raw() | vk::MemoryPropertyFlags::HOST_COHERENT.as_raw()
+        ),
+        preferred: vk::MemoryPropertyFlags::DEVICE_LOCAL,
+    };
+
+    // readbacks
+    pub const GPU_TO_CPU: Self = Self {
+        required: vk::MemoryPropertyFlags::from_raw(
+            vk::MemoryPropertyFlags::HOST_VISIBLE.as_raw() | vk::MemoryPropertyFlags::HOST_COHERENT.as_raw()
+        ),
+        preferred: vk::MemoryPropertyFlags::HOST_CACHED,
+    };
+}
+
+// a range of a block handed out to a single resource, given back when dropped
+#[derive(Debug)]
+pub struct Allocation {
+    pub memory: vk::DeviceMemory,
+    pub offset: vk::DeviceSize,
+    pub size: vk::DeviceSize,
+    pub memory_type: u32,
+    // null unless the memory is host visible
+    mapped: *mut u8,
+    block: usize,
+    // dangling once the allocator is dropped, which frees every block anyway
+    blocks: Weak<RefCell<Blocks>>,
+}
+
+impl Allocation {
+    // host visible blocks stay mapped for their whole lifetime
+    pub fn mapped_ptr(&self) -> Option<*mut u8> {
+        (!self.mapped.is_null()).then_some(self.mapped)
+    }
+
+    /// Copies `data` to the start of a mapped allocation, failing when it is unmapped or too small.
+    ///
+    /// # Safety
+    /// The gpu must not be reading or writing the memory while it is copied to.
+    pub unsafe fn write<T: Copy>(&self, data: &[T]) -> Result<()> {
+        let ptr = self.mapped_ptr().ok_or_else(|| anyhow!("Allocation is not host visible."))?;
+        let size = std::mem::size_of_val(data);
+
+        if size as vk::DeviceSize > self.size {
+            return Err(anyhow!("Write of {} bytes overflows allocation of {} bytes.", size, self.size));
+        }
+
+        ptr::copy_nonoverlapping(data.as_ptr() as *const u8, ptr, size);
+
+        Ok(())
+    }
+}
+
+impl Drop for Allocation {
+    fn drop(&mut self) {
+        if let Some(blocks) = self.blocks.upgrade() {
+            blocks.borrow_mut().free(self);
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct FreeRange {
+    offset: vk::DeviceSize,
+    size: vk::DeviceSize,
+}
+
+// offset and size bookkeeping of a block, kept apart from the memory so it can be tested alone
+#[derive(Debug)]
+struct FreeList {
+    size: vk::DeviceSize,
+    // sorted by offset and never adjacent, neighbours are merged on free
+    ranges: Vec<FreeRange>,
+    allocations: usize,
+}
+
+impl FreeList {
+    fn new(size: vk::DeviceSize) -> Self {
+        Self {
+            size,
+            ranges: vec![FreeRange { offset: 0, size }],
+            allocations: 0,
+        }
+    }
+
+    fn used(&self) -> vk::DeviceSize {
+        self.size - self.ranges.iter().map(|r| r.size).sum::<vk::DeviceSize>()
+    }
+
+    fn largest_free_range(&self) -> vk::DeviceSize {
+        self.ranges.iter().map(|r| r.size).max().unwrap_or(0)
+    }
+
+    // first fit, returns the aligned offset
+    fn allocate(&mut self, size: vk::DeviceSize, alignment: vk::DeviceSize) -> Option<vk::DeviceSize> {
+        let (index, offset) = self.ranges
+            .iter()
+            .enumerate()
+            .find_map(|(i, r)| {
+                let offset = align_up(r.offset, alignment);
+                (offset + size <= r.offset + r.size).then_some((i, offset))
+            })?;
+
+        let range = self.ranges.remove(index);
+
+        // whatever is left either side of the allocation goes back on the free list
+        let after = FreeRange { offset: offset + size, size: range.offset + range.size - (offset + size) };
+        if after.size > 0 {
+            self.ranges.insert(index, after);
+        }
+
+        let before = FreeRange { offset: range.offset, size: offset - range.offset };
+        if before.size > 0 {
+            self.ranges.insert(index, before);
+        }
+
+        self.allocations += 1;
+
+        Some(offset)
+    }
+
+    fn free(&mut self, offset: vk::DeviceSize, size: vk::DeviceSize) {
+        let index = self.ranges.partition_point(|r| r.offset < offset);
+        self.ranges.insert(index, FreeRange { offset, size });
+
+        // merge with the next range, then the previous one
+        if index + 1 < self.ranges.len() && self.ranges[index].offset + self.ranges[index].size == self.ranges[index + 1].offset {
+            self.ranges[index].size += self.ranges[index + 1].size;
+            self.ranges.remove(index + 1);
+        }
+
+        if index > 0 && self.ranges[index - 1].offset + self.ranges[index - 1].size == self.ranges[index].offset {
+            self.ranges[index - 1].size += self.ranges[index].size;
+            self.ranges.remove(index);
+        }
+
+        self.allocations -= 1;
+    }
+}
+
+struct Block {
+    memory: vk::DeviceMemory,
+    memory_type: u32,
+    mapped: *mut u8,
+    // linear and optimal resources never share a block, which sidesteps buffer image granularity
+    linear: bool,
+    // blocks made for a single oversized resource are freed as soon as it is
+    dedicated: bool,
+    free: FreeList,
+}
+
+impl Block {
+    // whether a resource of this memory type and tiling may be sub-allocated from the block
+    fn accepts(&self, memory_type: u32, linear: bool) -> bool {
+        self.memory_type == memory_type && self.linear == linear && !self.dedicated
+    }
+}
+
+// size of a new block for an allocation, and whether it is dedicated to it. anything over half a
+// block gets memory to itself rather than wasting the rest, smaller heaps get smaller blocks
+fn block_size(heap_size: vk::DeviceSize, size: vk::DeviceSize) -> (vk::DeviceSize, bool) {
+    let block_size = DEFAULT_BLOCK_SIZE.min(heap_size / 8);
+
+    match size > block_size / 2 {
+        true => (size, true),
+        false => (block_size, false),
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AllocatorStats {
+    pub blocks: usize,
+    pub allocations: usize,
+    pub reserved: vk::DeviceSize,
+    pub used: vk::DeviceSize,
+    pub free_ranges: usize,
+    pub largest_free_range: vk::DeviceSize,
+}
+
+impl AllocatorStats {
+    // 0 when all free memory is one contiguous range, approaching 1 as it gets split up
+    pub fn fragmentation(&self) -> f64 {
+        let free = self.reserved - self.used;
+        match free {
+            0 => 0.0,
+            _ => 1.0 - self.largest_free_range as f64 / free as f64,
+        }
+    }
+}
+
+impl fmt::Display for AllocatorStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} allocations in {} blocks, {:.2} / {:.2} MiB used, {} free ranges, {:.1}% fragmented",
+            self.allocations,
+            self.blocks,
+            self.used as f64 / (1024.0 * 1024.0),
+            self.reserved as f64 / (1024.0 * 1024.0),
+            self.free_ranges,
+            self.fragmentation() * 100.0,
+        )
+    }
+}
+
+// every block an allocator has made, shared with its allocations so they can free themselves
+struct Blocks {
+    device: Device,
+    // indices are handed out in allocations, so freed blocks leave a None behind
+    blocks: Vec<Option<Block>>,
+}
+
+impl Blocks {
+    fn free(&mut self, allocation: &Allocation) {
+        let Some(block) = self.blocks.get_mut(allocation.block).and_then(|b| b.as_mut()) else {
+            warn!("Freeing allocation from unknown memory block {}.", allocation.block);
+            return;
+        };
+
+        block.free.free(allocation.offset, allocation.size);
+
+        if block.dedicated && block.free.allocations == 0 {
+            let block = self.blocks[allocation.block].take().unwrap();
+            unsafe { self.device.free_memory(block.memory, None) };
+        }
+    }
+
+    fn stats(&self) -> AllocatorStats {
+        stats_of(self.blocks.iter().flatten().map(|b| &b.free))
+    }
+}
+
+impl Drop for Blocks {
+    fn drop(&mut self) {
+        let stats = self.stats();
+        if stats.allocations > 0 {
+            warn!("Destroying allocator with {} live allocations.", stats.allocations);
+        }
+
+        info!("Allocator: {}.", stats);
+
+        self.blocks
+            .drain(..)
+            .flatten()
+            .for_each(|b| unsafe { self.device.free_memory(b.memory, None) });
+    }
+}
+
+// sub-allocates resources out of large vkAllocateMemory blocks
+pub struct Allocator {
+    pub memory_properties: vk::PhysicalDeviceMemoryProperties,
+    device: Device,
+    blocks: Rc<RefCell<Blocks>>,
+}
+
+impl Allocator {
+    pub fn new(instance: &Instance, physical_device: vk::PhysicalDevice, device: &Device) -> Self {
+        let memory_properties = unsafe { instance.get_physical_device_memory_properties(physical_device) };
+
+        Self {
+            memory_properties,
+            device: device.clone(),
+            blocks: Rc::new(RefCell::new(Blocks {
+                device: device.clone(),
+                blocks: vec![],
+            })),
+        }
+    }
+
+    // compatible memory types, most preferred first
+    pub fn memory_types(&self, type_bits: u32, usage: MemoryUsage) -> Vec<u32> {
+        let mut types = (0..self.memory_properties.memory_type_count)
+            .filter(|i| {
+                let flags = self.memory_properties.memory_types[*i as usize].property_flags;
+                type_bits & (1 << i) != 0 && flags.contains(usage.required)
+            })
+            .collect::<Vec<_>>();
+
+        types.sort_by_key(|i| {
+            let flags = self.memory_properties.memory_types[*i as usize].property_flags;
+            std::cmp::Reverse((flags & usage.preferred).as_raw().count_ones())
+        });
+
+        types
+    }
+
+    /// Sub-allocates memory for a resource with `requirements`, or gives it a block of its own when
+    /// it is large.
+    ///
+    /// # Safety
+    /// `requirements` must be those of a buffer or image on the allocator's device, and `linear`
+    /// must say whether it is a buffer or linear image, as those never share a block with optimal
+    /// images.
+    pub unsafe fn allocate(
+        &mut self,
+        requirements: vk::MemoryRequirements,
+        usage: MemoryUsage,
+        linear: bool,
+    ) -> Result<Allocation> {
+        let types = self.memory_types(requirements.memory_type_bits, usage);
+
+        if types.is_empty() {
+            return Err(anyhow!("Failed to find suitable memory type for {:?}.", usage));
+        }
+
+        let owner = Rc::downgrade(&self.blocks);
+        let mut state = self.blocks.borrow_mut();
+        let blocks = &mut state.blocks;
+        let mut last_error = None;
+
+        for memory_type in types {
+            // existing blocks first
+            for (index, block) in blocks.iter_mut().enumerate() {
+                let Some(block) = block else { continue };
+
+                if !block.accepts(memory_type, linear) {
+                    continue;
+                }
+
+                if let Some(offset) = block.free.allocate(requirements.size, requirements.alignment) {
+                    return Ok(allocation_in(block, index, offset, requirements.size, owner));
+                }
+            }
+
+            // then a new block, falling through to the next memory type if this heap is full
+            match self.create_block(blocks, memory_type, requirements.size, linear) {
+                Ok(index) => {
+                    let block = blocks[index].as_mut().unwrap();
+                    let offset = block.free.allocate(requirements.size, requirements.alignment)
+                        .ok_or_else(|| anyhow!("New memory block can't fit allocation."))?;
+
+                    return Ok(allocation_in(block, index, offset, requirements.size, owner));
+                },
+                Err(e) => last_error = Some(e),
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| anyhow!("Failed to allocate memory.")))
+    }
+
+    unsafe fn create_block(
+        &self,
+        blocks: &mut Vec<Option<Block>>,
+        memory_type: u32,
+        size: vk::DeviceSize,
+        linear: bool,
+    ) -> Result<usize> {
+        let heap_index = self.memory_properties.memory_types[memory_type as usize].heap_index;
+        let heap_size = self.memory_properties.memory_heaps[heap_index as usize].size;
+        let (size, dedicated) = block_size(heap_size, size);
+
+        let memory_info = vk::MemoryAllocateInfo::default()
+            .allocation_size(size)
+            .memory_type_index(memory_type);
+
+        let memory = self.device.allocate_memory(&memory_info, None)?;
+
+        let flags = self.memory_properties.memory_types[memory_type as usize].property_flags;
+        let mapped = if flags.contains(vk::MemoryPropertyFlags::HOST_VISIBLE) {
+            match self.device.map_memory(memory, 0, vk::WHOLE_SIZE, vk::MemoryMapFlags::empty()) {
+                Ok(ptr) => ptr as *mut u8,
+                Err(e) => {
+                    self.device.free_memory(memory, None);
+                    return Err(e).context("Failed to map memory block.");
+                },
+            }
+        } else {
+            ptr::null_mut()
+        };
+
+        debug!("Allocated {} MiB memory block of type {} ({:?}).", size / (1024 * 1024), memory_type, flags);
+
+        let block = Block {
+            memory,
+            memory_type,
+            mapped,
+            linear,
+            dedicated,
+            free: FreeList::new(size),
+        };
+
+        // reuse the slot of a freed block where possible
+        match blocks.iter().position(|b| b.is_none()) {
+            Some(index) => {
+                blocks[index] = Some(block);
+                Ok(index)
+            },
+            None => {
+                blocks.push(Some(block));
+                Ok(blocks.len() - 1)
+            },
+        }
+    }
+
+    /// Creates a buffer and binds it to newly allocated memory.
+    ///
+    /// # Safety
+    /// `info` must describe a valid buffer for the allocator's device.
+    pub unsafe fn create_buffer(
+        &mut self,
+        info: &vk::BufferCreateInfo,
+        usage: MemoryUsage,
+    ) -> Result<(Owned<vk::Buffer>, Allocation)> {
+        let buffer = Owned::new(&self.device, self.device.create_buffer(info, None)?);
+        let requirements = self.device.get_buffer_memory_requirements(*buffer);
+
+        let allocation = self.allocate(requirements, usage, true)?;
+        self.device.bind_buffer_memory(*buffer, allocation.memory, allocation.offset)?;
+
+        Ok((buffer, allocation))
+    }
+
+    /// Creates an image and binds it to newly allocated memory.
+    ///
+    /// # Safety
+    /// `info` must describe a valid image for the allocator's device.
+    pub unsafe fn create_image(
+        &mut self,
+        info: &vk::ImageCreateInfo,
+        usage: MemoryUsage,
+    ) -> Result<(Owned<vk::Image>, Allocation)> {
+        let image = Owned::new(&self.device, self.device.create_image(info, None)?);
+        let requirements = self.device.get_image_memory_requirements(*image);
+
+        let linear = info.tiling == vk::ImageTiling::LINEAR;
+        let allocation = self.allocate(requirements, usage, linear)?;
+        self.device.bind_image_memory(*image, allocation.memory, allocation.offset)?;
+
+        Ok((image, allocation))
+    }
+
+    pub fn stats(&self) -> AllocatorStats {
+        self.blocks.borrow().stats()
+    }
+}
+
+fn stats_of<'a>(free_lists: impl Iterator<Item = &'a FreeList>) -> AllocatorStats {
+    free_lists.fold(AllocatorStats::default(), |mut stats, free| {
+        stats.blocks += 1;
+        stats.allocations += free.allocations;
+        stats.reserved += free.size;
+        stats.used += free.used();
+        stats.free_ranges += free.ranges.len();
+        stats.largest_free_range = stats.largest_free_range.max(free.largest_free_range());
+        stats
+    })
+}
+
+fn allocation_in(
+    block: &Block,
+    index: usize,
+    offset: vk::DeviceSize,
+    size: vk::DeviceSize,
+    blocks: Weak<RefCell<Blocks>>,
+) -> Allocation {
+    let mapped = match block.mapped.is_null() {
+        true => ptr::null_mut(),
+        false => unsafe { block.mapped.add(offset as usize) },
+    };
+
+    Allocation {
+        memory: block.memory,
+        offset,
+        size,
+        memory_type: block.memory_type,
+        mapped,
+        block: index,
+        blocks,
+    }
+}
+
+fn align_up(value: vk::DeviceSize, alignment: vk::DeviceSize) -> vk::DeviceSize {
+    match alignment {
+        0 => value,
+        _ => value.div_ceil(alignment) * alignment,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ranges(free: &FreeList) -> Vec<(vk::DeviceSize, vk::DeviceSize)> {
+        free.ranges.iter().map(|r| (r.offset, r.size)).collect()
+    }
+
+    fn block(memory_type: u32, linear: bool, dedicated: bool) -> Block {
+        Block {
+            memory: vk::DeviceMemory::null(),
+            memory_type,
+            mapped: std::ptr::null_mut(),
+            linear,
+            dedicated,
+            free: FreeList::new(1024),
+        }
+    }
+
+    #[test]
+    fn allocations_are_aligned() {
+        let mut free = FreeList::new(1024);
+
+        assert_eq!(free.allocate(10, 1), Some(0));
+        assert_eq!(free.allocate(16, 256), Some(256));
+
+        // the padding skipped for alignment stays free
+        assert_eq!(ranges(&free), vec![(10, 246), (272, 752)]);
+        assert_eq!(free.allocate(100, 4), Some(12));
+        assert_eq!(free.used(), 10 + 16 + 100);
+    }
+
+    #[test]
+    fn allocations_that_do_not_fit_fail() {
+        let mut free = FreeList::new(256);
+
+        assert_eq!(free.allocate(257, 1), None);
+        assert_eq!(free.allocate(200, 1), Some(0));
+        // enough bytes are free, but not once aligned
+        assert_eq!(free.allocate(50, 64), None);
+        assert_eq!(free.allocations, 1);
+    }
+
+    #[test]
+    fn freed_neighbours_are_merged() {
+        let mut free = FreeList::new(300);
+        let offsets: Vec<_> = (0..3).map(|_| free.allocate(100, 1).unwrap()).collect();
+        assert!(free.ranges.is_empty());
+
+        // merged with the previous range
+        free.free(offsets[0], 100);
+        free.free(offsets[1], 100);
+        assert_eq!(ranges(&free), vec![(0, 200)]);
+
+        let mut free = FreeList::new(300);
+        let offsets: Vec<_> = (0..3).map(|_| free.allocate(100, 1).unwrap()).collect();
+
+        // merged with the next range
+        free.free(offsets[2], 100);
+        free.free(offsets[1], 100);
+        assert_eq!(ranges(&free), vec![(100, 200)]);
+
+        // merged with both
+        let mut free = FreeList::new(300);
+        let offsets: Vec<_> = (0..3).map(|_| free.allocate(100, 1).unwrap()).collect();
+        free.free(offsets[0], 100);
+        free.free(offsets[2], 100);
+        assert_eq!(ranges(&free), vec![(0, 100), (200, 100)]);
+
+        free.free(offsets[1], 100);
+        assert_eq!(ranges(&free), vec![(0, 300)]);
+        assert_eq!((free.used(), free.allocations), (0, 0));
+    }
+
+    #[test]
+    fn freed_ranges_are_reused() {
+        let mut free = FreeList::new(1024);
+        let first = free.allocate(256, 1).unwrap();
+        let second = free.allocate(256, 1).unwrap();
+
+        free.free(first, 256);
+
+        // first fit goes back to the hole rather than the end of the block
+        assert_eq!(free.allocate(128, 1), Some(first));
+        assert_eq!(free.allocate(128, 1), Some(first + 128));
+        assert_eq!(free.allocate(128, 1), Some(second + 256));
+    }
+
+    #[test]
+    fn blocks_keep_linear_and_optimal_resources_apart() {
+        let shared = block(2, true, false);
+
+        assert!(shared.accepts(2, true));
+        assert!(!shared.accepts(2, false));
+        assert!(!shared.accepts(3, true));
+        assert!(!block(2, true, true).accepts(2, true));
+    }
+
+    #[test]
+    fn large_allocations_get_dedicated_blocks() {
+        let heap_size = 16 * DEFAULT_BLOCK_SIZE;
+
+        assert_eq!(block_size(heap_size, 1024), (DEFAULT_BLOCK_SIZE, false));
+        assert_eq!(block_size(heap_size, DEFAULT_BLOCK_SIZE / 2), (DEFAULT_BLOCK_SIZE, false));
+        assert_eq!(block_size(heap_size, DEFAULT_BLOCK_SIZE / 2 + 1), (DEFAULT_BLOCK_SIZE / 2 + 1, true));
+
+        // a small heap gets blocks an eighth of its size, and the threshold shrinks with them
+        assert_eq!(block_size(8 * 1024, 512), (1024, false));
+        assert_eq!(block_size(8 * 1024, 513), (513, true));
+    }
+
+    #[test]
+    fn fragmentation_follows_the_largest_free_range() {
+        let mut free = FreeList::new(400);
+        let offsets: Vec<_> = (0..4).map(|_| free.allocate(100, 1).unwrap()).collect();
+        assert_eq!(stats_of([&free].into_iter()).fragmentation(), 0.0);
+
+        free.free(offsets[0], 100);
+        free.free(offsets[2], 100);
+
+        let stats = stats_of([&free].into_iter());
+        assert_eq!((stats.used, stats.free_ranges, stats.largest_free_range), (200, 2, 100));
+        assert_eq!(stats.fragmentation(), 0.5);
+
+        free.free(offsets[1], 100);
+        assert_eq!(stats_of([&free].into_iter()).fragmentation(), 0.0);
+    }
+}
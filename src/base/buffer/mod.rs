@@ -0,0 +1,344 @@
+use std::marker::PhantomData;
+
+use ash::{vk, Device};
+
+use anyhow::{anyhow, Result};
+
+use super::allocator::{Allocation, Allocator, MemoryUsage};
+use super::owned::Owned;
+
+// a vertex type that can describe its own layout to the pipeline
+pub trait Vertex: Copy {
+    fn binding_description(binding: u32) -> vk::VertexInputBindingDescription;
+    fn attribute_descriptions(binding: u32) -> Vec<vk::VertexInputAttributeDescription>;
+}
+
+// index types vkCmdBindIndexBuffer understands
+pub trait Index: Copy {
+    const INDEX_TYPE: vk::IndexType;
+}
+
+impl Index for u16 {
+    const INDEX_TYPE: vk::IndexType = vk::IndexType::UINT16;
+}
+
+impl Index for u32 {
+    const INDEX_TYPE: vk::IndexType = vk::IndexType::UINT32;
+}
+
+// the vertex input state of a pipeline, one binding per vertex type
+#[derive(Debug, Clone, Default)]
+pub struct VertexLayout {
+    pub bindings: Vec<vk::VertexInputBindingDescription>,
+    pub attributes: Vec<vk::VertexInputAttributeDescription>,
+}
+
+impl VertexLayout {
+    pub fn of<V: Vertex>() -> Self {
+        Self::default().with::<V>()
+    }
+
+    // adds V as the next binding. its attribute locations follow on from those already added, so
+    // e.g. a second vertex type's location 0 is read from after the first type's attributes
+    pub fn with<V: Vertex>(mut self) -> Self {
+        let binding = self.bindings.len() as u32;
+        let first_location = self.attributes.len() as u32;
+
+        self.bindings.push(V::binding_description(binding));
+        self.attributes.extend(
+            V::attribute_descriptions(binding)
+                .into_iter()
+                .map(|a| a.location(a.location + first_location))
+        );
+        self
+    }
+
+    pub fn input_state(&self) -> vk::PipelineVertexInputStateCreateInfo<'_> {
+        vk::PipelineVertexInputStateCreateInfo::default()
+            .vertex_binding_descriptions(&self.bindings)
+            .vertex_attribute_descriptions(&self.attributes)
+    }
+}
+
+// the buffer is declared first so it is destroyed before its memory is freed
+pub struct BufferData {
+    pub buffer: Owned<vk::Buffer>,
+    pub allocation: Allocation,
+    pub size: vk::DeviceSize,
+}
+
+impl BufferData {
+    /// Creates a buffer of `size` bytes in memory suited to `memory_usage`.
+    ///
+    /// # Safety
+    /// The buffer must be dropped before the allocator's device is destroyed.
+    pub unsafe fn create(
+        allocator: &mut Allocator,
+        size: vk::DeviceSize,
+        usage: vk::BufferUsageFlags,
+        memory_usage: MemoryUsage,
+    ) -> Result<Self> {
+        let buffer_info = vk::BufferCreateInfo::default()
+            .size(size)
+            .usage(usage)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE);
+
+        let (buffer, allocation) = allocator.create_buffer(&buffer_info, memory_usage)?;
+
+        Ok(Self { buffer, allocation, size })
+    }
+}
+
+pub struct VertexBuffer<V: Vertex> {
+    pub data: BufferData,
+    pub count: u32,
+    marker: PhantomData<V>,
+}
+
+impl<V: Vertex> VertexBuffer<V> {
+    /// Uploads `vertices` to a device-local vertex buffer, waiting for the copy to finish.
+    ///
+    /// # Safety
+    /// `queue` and `command_pool` must belong to `device`, the device `allocator` uses, and the
+    /// pool's queue family must support transfers.
+    pub unsafe fn create(
+        device: &Device,
+        allocator: &mut Allocator,
+        queue: vk::Queue,
+        command_pool: vk::CommandPool,
+        vertices: &[V],
+    ) -> Result<Self> {
+        let data = upload_buffer(device, allocator, queue, command_pool, vertices, vk::BufferUsageFlags::VERTEX_BUFFER)?;
+
+        Ok(
+            Self {
+                data,
+                count: vertices.len() as u32,
+                marker: PhantomData,
+            }
+        )
+    }
+
+    /// Binds the buffer to vertex input `binding`.
+    ///
+    /// # Safety
+    /// `command_buffer` must be recording, and the buffer must stay alive until it has executed.
+    pub unsafe fn bind(&self, device: &Device, command_buffer: vk::CommandBuffer, binding: u32) {
+        device.cmd_bind_vertex_buffers(command_buffer, binding, &[*self.data.buffer], &[0]);
+    }
+}
+
+pub struct IndexBuffer<I: Index> {
+    pub data: BufferData,
+    pub count: u32,
+    marker: PhantomData<I>,
+}
+
+impl<I: Index> IndexBuffer<I> {
+    /// Uploads `indices` to a device-local index buffer, waiting for the copy to finish.
+    ///
+    /// # Safety
+    /// `queue` and `command_pool` must belong to `device`, which `allocator` must also use, and the
+    /// pool's queue family must support transfers.
+    pub unsafe fn create(
+        device: &Device,
+        allocator: &mut Allocator,
+        queue: vk::Queue,
+        command_pool: vk::CommandPool,
+        indices: &[I],
+    ) -> Result<Self> {
+        let data = upload_buffer(device, allocator, queue, command_pool, indices, vk::BufferUsageFlags::INDEX_BUFFER)?;
+
+        Ok(
+            Self {
+                data,
+                count: indices.len() as u32,
+                marker: PhantomData,
+            }
+        )
+    }
+
+    /// Binds the buffer as the index buffer, with the index type of `I`.
+    ///
+    /// # Safety
+    /// `command_buffer` must be recording, and the buffer must outlive its execution.
+    pub unsafe fn bind(&self, device: &Device, command_buffer: vk::CommandBuffer) {
+        device.cmd_bind_index_buffer(command_buffer, *self.data.buffer, 0, I::INDEX_TYPE);
+    }
+}
+
+// indexed geometry drawn with a single vertex binding
+pub struct Mesh<V: Vertex, I: Index = u16> {
+    pub vertices: VertexBuffer<V>,
+    pub indices: IndexBuffer<I>,
+}
+
+impl<V: Vertex, I: Index> Mesh<V, I> {
+    /// Uploads the vertices and indices of a mesh, waiting for both copies to finish.
+    ///
+    /// # Safety
+    /// `queue` and `command_pool` must belong to `device`, the device `allocator` uses, and the
+    /// pool's queue family must support transfers.
+    pub unsafe fn create(
+        device: &Device,
+        allocator: &mut Allocator,
+        queue: vk::Queue,
+        command_pool: vk::CommandPool,
+        vertices: &[V],
+        indices: &[I],
+    ) -> Result<Self> {
+        let vertices = VertexBuffer::create(device, allocator, queue, command_pool, vertices)?;
+        let indices = IndexBuffer::create(device, allocator, queue, command_pool, indices)?;
+
+        Ok(Self { vertices, indices })
+    }
+
+    pub fn layout() -> VertexLayout {
+        VertexLayout::of::<V>()
+    }
+
+    /// Binds the mesh's buffers and draws every index once.
+    ///
+    /// # Safety
+    /// `command_buffer` must be recording inside a render pass, with a pipeline bound that reads
+    /// `V` from binding 0.
+    pub unsafe fn draw(&self, device: &Device, command_buffer: vk::CommandBuffer) {
+        self.vertices.bind(device, command_buffer, 0);
+        self.indices.bind(device, command_buffer);
+        device.cmd_draw_indexed(command_buffer, self.indices.count, 1, 0, 0, 0);
+    }
+}
+
+/// Copies `data` into a new device-local buffer through a host-visible staging buffer.
+///
+/// # Safety
+/// `queue` and `command_pool` must belong to `device`, which `allocator` must also use. The queue
+/// is waited on before this returns.
+pub unsafe fn upload_buffer<T: Copy>(
+    device: &Device,
+    allocator: &mut Allocator,
+    queue: vk::Queue,
+    command_pool: vk::CommandPool,
+    data: &[T],
+    usage: vk::BufferUsageFlags,
+) -> Result<BufferData> {
+    let size = std::mem::size_of_val(data) as vk::DeviceSize;
+
+    // zero sized buffers aren't allowed
+    if size == 0 {
+        return Err(anyhow!("Can't upload an empty buffer."));
+    }
+
+    let staging = BufferData::create(allocator, size, vk::BufferUsageFlags::TRANSFER_SRC, MemoryUsage::CPU_ONLY)?;
+    staging.allocation.write(data)?;
+
+    let buffer = BufferData::create(allocator, size, usage | vk::BufferUsageFlags::TRANSFER_DST, MemoryUsage::GPU_ONLY)?;
+
+    let region = vk::BufferCopy::default()
+        .src_offset(0)
+        .dst_offset(0)
+        .size(size);
+
+    // waits for the copy, so the staging buffer can be dropped straight after
+    super::single_time_commands(device, queue, command_pool, |command_buffer| {
+        device.cmd_copy_buffer(command_buffer, *staging.buffer, *buffer.buffer, &[region]);
+    })?;
+
+    Ok(buffer)
+}
+
+// position and colour only, for shaders without textures
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorVertex {
+    pub pos: [f32; 2],
+    pub color: [f32; 3],
+}
+
+impl Vertex for ColorVertex {
+    fn binding_description(binding: u32) -> vk::VertexInputBindingDescription {
+        vk::VertexInputBindingDescription::default()
+            .binding(binding)
+            .stride(std::mem::size_of::<Self>() as u32)
+            .input_rate(vk::VertexInputRate::VERTEX)
+    }
+
+    fn attribute_descriptions(binding: u32) -> Vec<vk::VertexInputAttributeDescription> {
+        vec![
+            vk::VertexInputAttributeDescription::default()
+                .binding(binding)
+                .location(0)
+                .format(vk::Format::R32G32_SFLOAT)
+                .offset(std::mem::offset_of!(Self, pos) as u32),
+            vk::VertexInputAttributeDescription::default()
+                .binding(binding)
+                .location(1)
+                .format(vk::Format::R32G32B32_SFLOAT)
+                .offset(std::mem::offset_of!(Self, color) as u32),
+        ]
+    }
+}
+
+// position, colour and texture coordinates, matching the inputs of shader.vert
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TexturedVertex {
+    pub pos: [f32; 2],
+    pub color: [f32; 3],
+    pub tex_coord: [f32; 2],
+}
+
+impl Vertex for TexturedVertex {
+    fn binding_description(binding: u32) -> vk::VertexInputBindingDescription {
+        vk::VertexInputBindingDescription::default()
+            .binding(binding)
+            .stride(std::mem::size_of::<Self>() as u32)
+            .input_rate(vk::VertexInputRate::VERTEX)
+    }
+
+    fn attribute_descriptions(binding: u32) -> Vec<vk::VertexInputAttributeDescription> {
+        vec![
+            vk::VertexInputAttributeDescription::default()
+                .binding(binding)
+                .location(0)
+                .format(vk::Format::R32G32_SFLOAT)
+                .offset(std::mem::offset_of!(Self, pos) as u32),
+            vk::VertexInputAttributeDescription::default()
+                .binding(binding)
+                .location(1)
+                .format(vk::Format::R32G32B32_SFLOAT)
+                .offset(std::mem::offset_of!(Self, color) as u32),
+            vk::VertexInputAttributeDescription::default()
+                .binding(binding)
+                .location(2)
+                .format(vk::Format::R32G32_SFLOAT)
+                .offset(std::mem::offset_of!(Self, tex_coord) as u32),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn locations(layout: &VertexLayout) -> Vec<(u32, u32)> {
+        layout.attributes.iter().map(|a| (a.binding, a.location)).collect()
+    }
+
+    #[test]
+    fn single_vertex_type_keeps_its_locations() {
+        let layout = VertexLayout::of::<TexturedVertex>();
+
+        assert_eq!(layout.bindings.len(), 1);
+        assert_eq!(locations(&layout), [(0, 0), (0, 1), (0, 2)]);
+    }
+
+    #[test]
+    fn combined_vertex_types_do_not_share_locations() {
+        let layout = VertexLayout::of::<TexturedVertex>().with::<ColorVertex>();
+
+        assert_eq!(layout.bindings.iter().map(|b| b.binding).collect::<Vec<_>>(), [0, 1]);
+        assert_eq!(layout.bindings[1].stride, std::mem::size_of::<ColorVertex>() as u32);
+        assert_eq!(locations(&layout), [(0, 0), (0, 1), (0, 2), (1, 3), (1, 4)]);
+    }
+}
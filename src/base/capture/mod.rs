@@ -0,0 +1,219 @@
+use std::{fs::File, io::BufWriter, path::Path};
+
+use ash::{vk, Device};
+
+use anyhow::{anyhow, Result};
+
+use super::{
+    allocator::{Allocation, Allocator, MemoryUsage},
+    data,
+    owned::Owned,
+};
+
+// rgba8 pixels read back from a rendered frame
+#[derive(Debug, Clone)]
+pub struct FrameCapture {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl FrameCapture {
+    // converts tightly packed texels in the given format into rgba8
+    pub fn from_raw(format: vk::Format, extent: vk::Extent2D, bytes: &[u8]) -> Result<Self> {
+        let swizzle = needs_swizzle(format)?;
+
+        let size = extent.width as usize * extent.height as usize * 4;
+        if bytes.len() < size {
+            return Err(anyhow!("Capture buffer too small ({} < {}).", bytes.len(), size));
+        }
+
+        let mut pixels = bytes[..size].to_vec();
+        if swizzle {
+            pixels.chunks_exact_mut(4).for_each(|p| p.swap(0, 2));
+        }
+
+        Ok(
+            Self {
+                width: extent.width,
+                height: extent.height,
+                pixels,
+            }
+        )
+    }
+
+    pub fn save_png(&self, path: impl AsRef<Path>) -> Result<()> {
+        let file = File::create(path.as_ref())?;
+        let mut encoder = png::Encoder::new(BufWriter::new(file), self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+
+        // the formats we read back from are sRGB encoded already
+        encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.pixels)?;
+
+        Ok(())
+    }
+}
+
+// whether the red and blue channels need swapping to get rgba, errors for formats we can't read
+fn needs_swizzle(format: vk::Format) -> Result<bool> {
+    match format {
+        vk::Format::B8G8R8A8_SRGB | vk::Format::B8G8R8A8_UNORM => Ok(true),
+        vk::Format::R8G8B8A8_SRGB | vk::Format::R8G8B8A8_UNORM => Ok(false),
+        _ => Err(anyhow!("Unsupported capture format {:?}.", format)),
+    }
+}
+
+// host-visible buffer a frame gets copied into. the copy must have finished before it is
+// dropped, which frees the command buffer back to its pool
+pub struct ReadbackData {
+    pub buffer: Owned<vk::Buffer>,
+    pub allocation: Allocation,
+    pub command_buffer: vk::CommandBuffer,
+    pub size: vk::DeviceSize,
+    command_pool: vk::CommandPool,
+    device: Device,
+}
+
+impl Drop for ReadbackData {
+    fn drop(&mut self) {
+        unsafe { self.device.free_command_buffers(self.command_pool, &[self.command_buffer]) };
+    }
+}
+
+// creates the readback buffer and records the copy out of the target image, to be
+// submitted straight after the frame's own command buffer
+pub(super) fn create_readback(
+    device: &Device,
+    allocator: &mut Allocator,
+    command_pool: vk::CommandPool,
+    target: &data::RenderTarget,
+    image: vk::Image,
+) -> Result<ReadbackData> {
+    // bail before rendering anything we couldn't convert
+    needs_swizzle(target.format())?;
+
+    let extent = target.extent();
+    let size = extent.width as vk::DeviceSize * extent.height as vk::DeviceSize * 4;
+
+    let buffer_info = vk::BufferCreateInfo::default()
+        .size(size)
+        .usage(vk::BufferUsageFlags::TRANSFER_DST)
+        .sharing_mode(vk::SharingMode::EXCLUSIVE);
+
+    let (buffer, allocation) = unsafe { allocator.create_buffer(&buffer_info, MemoryUsage::GPU_TO_CPU)? };
+
+    let allocate_info = vk::CommandBufferAllocateInfo::default()
+        .command_pool(command_pool)
+        .level(vk::CommandBufferLevel::PRIMARY)
+        .command_buffer_count(1);
+
+    let readback = ReadbackData {
+        buffer,
+        allocation,
+        command_buffer: unsafe { device.allocate_command_buffers(&allocate_info)?[0] },
+        size,
+        command_pool,
+        device: device.clone(),
+    };
+    let command_buffer = readback.command_buffer;
+    let buffer = *readback.buffer;
+
+    let subresource_range = vk::ImageSubresourceRange::default()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .base_mip_level(0)
+        .level_count(1)
+        .base_array_layer(0)
+        .layer_count(1);
+
+    // wait for the render pass to finish writing before reading the image
+    let to_transfer = vk::ImageMemoryBarrier::default()
+        .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+        .dst_access_mask(vk::AccessFlags::TRANSFER_READ)
+        .old_layout(target.final_layout())
+        .new_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .image(image)
+        .subresource_range(subresource_range);
+
+    // and put it back how the presentation engine expects it
+    let from_transfer = vk::ImageMemoryBarrier::default()
+        .src_access_mask(vk::AccessFlags::TRANSFER_READ)
+        .dst_access_mask(vk::AccessFlags::empty())
+        .old_layout(vk::ImageLayout::TRANSFER_SRC_OPTIMAL)
+        .new_layout(target.final_layout())
+        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .image(image)
+        .subresource_range(subresource_range);
+
+    let to_host = vk::BufferMemoryBarrier::default()
+        .src_access_mask(vk::AccessFlags::TRANSFER_WRITE)
+        .dst_access_mask(vk::AccessFlags::HOST_READ)
+        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .buffer(buffer)
+        .offset(0)
+        .size(size);
+
+    let region = vk::BufferImageCopy::default()
+        .buffer_offset(0)
+        .buffer_row_length(0)
+        .buffer_image_height(0)
+        .image_subresource(
+            vk::ImageSubresourceLayers::default()
+                .aspect_mask(vk::ImageAspectFlags::COLOR)
+                .mip_level(0)
+                .base_array_layer(0)
+                .layer_count(1)
+        )
+        .image_offset(vk::Offset3D::default())
+        .image_extent(vk::Extent3D { width: extent.width, height: extent.height, depth: 1 });
+
+    let begin_info = vk::CommandBufferBeginInfo::default()
+        .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+
+    unsafe {
+        device.begin_command_buffer(command_buffer, &begin_info)?;
+        // transfer is included to chain onto the render pass's outgoing dependency
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[],
+            &[to_transfer],
+        );
+        device.cmd_copy_image_to_buffer(command_buffer, image, vk::ImageLayout::TRANSFER_SRC_OPTIMAL, buffer, &[region]);
+        device.cmd_pipeline_barrier(
+            command_buffer,
+            vk::PipelineStageFlags::TRANSFER,
+            vk::PipelineStageFlags::HOST | vk::PipelineStageFlags::BOTTOM_OF_PIPE,
+            vk::DependencyFlags::empty(),
+            &[],
+            &[to_host],
+            &[from_transfer],
+        );
+        device.end_command_buffer(command_buffer)?;
+    }
+
+    Ok(readback)
+}
+
+// reads the persistently mapped buffer once its frame's fence has signalled
+pub(super) unsafe fn read_capture(
+    target: &data::RenderTarget,
+    readback: &ReadbackData,
+) -> Result<FrameCapture> {
+    let ptr = readback.allocation
+        .mapped_ptr()
+        .ok_or_else(|| anyhow!("Readback buffer is not host visible."))?;
+    let bytes = std::slice::from_raw_parts(ptr as *const u8, readback.size as usize);
+
+    FrameCapture::from_raw(target.format(), target.extent(), bytes)
+}
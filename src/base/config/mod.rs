@@ -0,0 +1,54 @@
+use std::path::PathBuf;
+
+use ash::vk;
+
+use crate::util::constants::*;
+
+use super::device::DeviceSelector;
+use super::pipeline_cache;
+
+// startup options for App
+#[derive(Debug, Clone)]
+pub struct Config {
+    // forces a physical device, falls back to the VULKAN_DEVICE environment variable
+    // and then to the highest scoring device
+    pub device: Option<DeviceSelector>,
+    pub depth: DepthConfig,
+    // msaa sample count, 1 to disable. clamped to what the device supports
+    pub msaa_samples: u32,
+    // file the pipeline cache is loaded from and saved to, None to not persist it
+    pub pipeline_cache: Option<PathBuf>,
+    // length of the frame ring, 1 to MAX_FRAMES_IN_FLIGHT. more hides cpu spikes at the
+    // cost of latency
+    pub frames_in_flight: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            device: None,
+            depth: DepthConfig::default(),
+            msaa_samples: 1,
+            pipeline_cache: pipeline_cache::default_path(),
+            frames_in_flight: DEFAULT_FRAMES_IN_FLIGHT,
+        }
+    }
+}
+
+// depth-stencil state of the scene pipeline
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DepthConfig {
+    pub test: bool,
+    pub write: bool,
+    pub compare_op: vk::CompareOp,
+}
+
+impl Default for DepthConfig {
+    fn default() -> Self {
+        Self {
+            test: true,
+            write: true,
+            compare_op: vk::CompareOp::LESS,
+        }
+    }
+}
@@ -2,19 +2,38 @@ use ash::{ext::debug_utils, khr::{swapchain, surface}, vk::{self, SurfaceKHR, Sw
 
 use anyhow::{Result, anyhow};
 
+use glam::Mat4;
+
+use super::allocator::Allocation;
+use super::buffer::BufferData;
+use super::owned::Owned;
+
 pub struct DebugData {
     pub utils_loader: debug_utils::Instance,
     pub callback: vk::DebugUtilsMessengerEXT,
 }
 
+impl Drop for DebugData {
+    fn drop(&mut self) {
+        unsafe { self.utils_loader.destroy_debug_utils_messenger(self.callback, None) };
+    }
+}
+
 pub struct SurfaceData {
     pub surface: SurfaceKHR,
     pub loader: surface::Instance,
 }
 
+impl Drop for SurfaceData {
+    fn drop(&mut self) {
+        unsafe { self.loader.destroy_surface(self.surface, None) };
+    }
+}
+
 pub struct PhysicalDeviceData {
     pub device: vk::PhysicalDevice,
-    pub swapchain_support: SwapchainSupport,
+    // only queried when rendering to a surface
+    pub swapchain_support: Option<SwapchainSupport>,
 }
 
 pub struct QueueData {
@@ -24,6 +43,11 @@ pub struct QueueData {
 }
 
 impl QueueData {
+    /// Fetches the first graphics and present queues of the chosen families.
+    ///
+    /// # Safety
+    /// `logical_device` must have been created with a queue from each family in
+    /// `queue_family_indices`.
     pub unsafe fn get(
         queue_family_indices: QueueFamilyIndices,
         logical_device: &Device,
@@ -36,25 +60,137 @@ impl QueueData {
     }
 }
 
+// the images belong to the swapchain, only the views are ours
 pub struct SwapchainData {
     pub swapchain: SwapchainKHR,
     pub loader: swapchain::Device,
     pub format: vk::Format,
     pub extent: vk::Extent2D,
     pub images: Vec<vk::Image>,
-    pub image_views: Vec<vk::ImageView>,
+    pub image_views: Vec<Owned<vk::ImageView>>,
+}
+
+impl Drop for SwapchainData {
+    fn drop(&mut self) {
+        self.image_views.clear();
+        unsafe { self.loader.destroy_swapchain(self.swapchain, None) };
+    }
+}
+
+// device-local images rendered to instead of a swapchain when running headless.
+// fields drop in declaration order, so views go before their images and images before
+// their memory, here and in the other resource structs
+pub struct OffscreenData {
+    pub format: vk::Format,
+    pub extent: vk::Extent2D,
+    pub image_views: Vec<Owned<vk::ImageView>>,
+    pub images: Vec<Owned<vk::Image>>,
+    pub allocations: Vec<Allocation>,
+}
+
+// a depth or multisampled colour image shared by every framebuffer,
+// recreated along with the render target
+pub struct AttachmentImage {
+    pub format: vk::Format,
+    pub samples: vk::SampleCountFlags,
+    pub view: Owned<vk::ImageView>,
+    pub image: Owned<vk::Image>,
+    pub allocation: Allocation,
+}
+
+pub enum RenderTarget {
+    Swapchain(SwapchainData),
+    Offscreen(OffscreenData),
+}
+
+impl RenderTarget {
+    pub fn format(&self) -> vk::Format {
+        match self {
+            RenderTarget::Swapchain(swapchain_data) => swapchain_data.format,
+            RenderTarget::Offscreen(offscreen_data) => offscreen_data.format,
+        }
+    }
+
+    pub fn extent(&self) -> vk::Extent2D {
+        match self {
+            RenderTarget::Swapchain(swapchain_data) => swapchain_data.extent,
+            RenderTarget::Offscreen(offscreen_data) => offscreen_data.extent,
+        }
+    }
+
+    pub fn image_count(&self) -> usize {
+        match self {
+            RenderTarget::Swapchain(swapchain_data) => swapchain_data.images.len(),
+            RenderTarget::Offscreen(offscreen_data) => offscreen_data.images.len(),
+        }
+    }
+
+    pub fn image(&self, index: usize) -> vk::Image {
+        match self {
+            RenderTarget::Swapchain(swapchain_data) => swapchain_data.images[index],
+            RenderTarget::Offscreen(offscreen_data) => *offscreen_data.images[index],
+        }
+    }
+
+    pub fn image_view(&self, index: usize) -> vk::ImageView {
+        match self {
+            RenderTarget::Swapchain(swapchain_data) => *swapchain_data.image_views[index],
+            RenderTarget::Offscreen(offscreen_data) => *offscreen_data.image_views[index],
+        }
+    }
+
+    // layout the render pass leaves the color attachment in
+    pub fn final_layout(&self) -> vk::ImageLayout {
+        match self {
+            RenderTarget::Swapchain(_) => vk::ImageLayout::PRESENT_SRC_KHR,
+            RenderTarget::Offscreen(_) => vk::ImageLayout::TRANSFER_SRC_OPTIMAL,
+        }
+    }
+}
+
+// per-frame shader data, bound at set 0 binding 0
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UniformBufferObject {
+    pub model: Mat4,
+    pub view: Mat4,
+    pub proj: Mat4,
+}
+
+impl Default for UniformBufferObject {
+    // identity transforms draw vertices straight in clip space
+    fn default() -> Self {
+        Self {
+            model: Mat4::IDENTITY,
+            view: Mat4::IDENTITY,
+            proj: Mat4::IDENTITY,
+        }
+    }
 }
 
 pub struct PipelineData {
-    pub pipeline: vk::Pipeline,
-    pub layout: vk::PipelineLayout,
+    pub pipeline: Owned<vk::Pipeline>,
+    pub layout: Owned<vk::PipelineLayout>,
 }
 
-pub struct SyncObjects {
-    pub image_available_semaphores: Vec<vk::Semaphore>,
-    pub render_finished_semaphores: Vec<vk::Semaphore>,
-    pub in_flight_fences: Vec<vk::Fence>,
-    pub images_in_flight: Vec<vk::Fence>,
+// everything one frame in flight records and submits with, reused each time the ring comes
+// back round to it once its fence has signalled
+pub struct FrameContext {
+    // allocated from command_pool, which is reset rather than the buffer
+    pub command_buffer: vk::CommandBuffer,
+    pub command_pool: Owned<vk::CommandPool>,
+    // signalled by acquiring a swapchain image, waited on by the submission
+    pub image_available: Owned<vk::Semaphore>,
+    // signalled by the submission, waited on by present
+    pub render_finished: Owned<vk::Semaphore>,
+    // created signalled, so the first wait on it returns straight away
+    pub in_flight: Owned<vk::Fence>,
+    // the frame's copy of the scene uniforms
+    pub uniform_buffer: BufferData,
+    // points at uniform_buffer and the current texture
+    pub descriptor_set: vk::DescriptorSet,
+    // still points at a retired texture, rewritten before the frame is next recorded
+    pub stale_descriptor_set: bool,
 }
 
 #[derive(Debug, Clone, Default)]
@@ -65,6 +201,10 @@ pub struct SwapchainSupport {
 }
 
 impl SwapchainSupport {
+    /// Queries what swapchains the surface supports on `physical_device`.
+    ///
+    /// # Safety
+    /// `physical_device` must belong to the instance the surface was created from.
     pub unsafe fn get(
         surface_data: &SurfaceData,
         physical_device: vk::PhysicalDevice
@@ -77,7 +217,8 @@ impl SwapchainSupport {
         })
     }
 
-    pub fn get_surface_format(&self) -> vk::SurfaceFormatKHR {
+    // None when the surface reports no formats at all
+    pub fn get_surface_format(&self) -> Option<vk::SurfaceFormatKHR> {
         self.formats
             .iter()
             .cloned()
@@ -85,7 +226,7 @@ impl SwapchainSupport {
                 f.format == vk::Format::B8G8R8A8_SRGB &&
                 f.color_space == vk::ColorSpaceKHR::SRGB_NONLINEAR
             })
-            .unwrap_or_else(|| self.formats[0])
+            .or_else(|| self.formats.first().cloned())
     }
 
     pub fn get_present_mode(&self) -> vk::PresentModeKHR {
@@ -120,7 +261,12 @@ pub struct QueueFamilyIndices {
 }
 
 impl QueueFamilyIndices {
-    pub unsafe fn get(instance: &Instance, surface_data: &SurfaceData, phys_device: vk::PhysicalDevice) -> Result<Self> {
+    /// Finds a graphics queue family, and one that can present to the surface. Without a surface
+    /// there is nothing to present to, so the graphics family stands in.
+    ///
+    /// # Safety
+    /// `phys_device` must belong to `instance`, and so must the surface if there is one.
+    pub unsafe fn get(instance: &Instance, surface_data: Option<&SurfaceData>, phys_device: vk::PhysicalDevice) -> Result<Self> {
         let properties = instance.get_physical_device_queue_family_properties(phys_device);
 
         let graphics = properties
@@ -128,13 +274,19 @@ impl QueueFamilyIndices {
             .position(|p| p.queue_flags.contains(vk::QueueFlags::GRAPHICS))
             .map(|i| i as u32);
 
-        let mut present = None;
-        for (index, _properties) in properties.iter().enumerate() {
-            if surface_data.loader.get_physical_device_surface_support(phys_device, index as u32, surface_data.surface)? {
-                present = Some(index as u32);
-                break;
-            }
-        }
+        let present = match surface_data {
+            Some(surface_data) => {
+                let mut present = None;
+                for (index, _properties) in properties.iter().enumerate() {
+                    if surface_data.loader.get_physical_device_surface_support(phys_device, index as u32, surface_data.surface)? {
+                        present = Some(index as u32);
+                        break;
+                    }
+                }
+                present
+            },
+            None => graphics,
+        };
 
         if let (Some(graphics), Some(present)) = (graphics, present) {
             Ok(Self { graphics, present })
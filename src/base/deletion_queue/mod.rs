@@ -0,0 +1,168 @@
+use std::{any::Any, collections::VecDeque};
+
+use anyhow::Result;
+
+// keeps replaced resources alive until the gpu is done with them. every submission gets a serial,
+// and a resource retired after submission n is dropped once submissions up to n have signalled
+// their in flight fence, so nothing has to wait for the whole device to go idle
+pub struct DeletionQueue {
+    // serial of the last submission made with each frame's fence, 0 before the first
+    fence_serials: Vec<u64>,
+    serial: u64,
+    // oldest first, as serials only ever go up
+    retired: VecDeque<(u64, Box<dyn Any>)>,
+}
+
+impl DeletionQueue {
+    pub fn new(frames_in_flight: usize) -> Self {
+        Self {
+            fence_serials: vec![0; frames_in_flight],
+            serial: 0,
+            retired: VecDeque::new(),
+        }
+    }
+
+    // anything that destroys itself when dropped, e.g. an Owned handle or a whole PipelineData
+    pub fn retire<T: 'static>(&mut self, resource: T) {
+        self.retired.push_back((self.serial, Box::new(resource)));
+    }
+
+    // records a submission that signals the frame's in flight fence
+    pub fn submitted(&mut self, frame: usize) {
+        self.serial += 1;
+        self.fence_serials[frame] = self.serial;
+    }
+
+    // drops whatever no pending submission can still be using. signalled says whether a frame's
+    // in flight fence has signalled, normally by asking the device for its status
+    pub fn collect(&mut self, mut signalled: impl FnMut(usize) -> Result<bool>) -> Result<()> {
+        if self.retired.is_empty() {
+            return Ok(());
+        }
+
+        // every submission before the oldest one still executing has finished
+        let mut completed = self.serial;
+        for (frame, serial) in self.fence_serials.iter().enumerate() {
+            if *serial > 0 && !signalled(frame)? {
+                completed = completed.min(serial - 1);
+            }
+        }
+
+        while self.retired.front().is_some_and(|(serial, _)| *serial <= completed) {
+            self.retired.pop_front();
+        }
+
+        Ok(())
+    }
+
+    // puts value in slot, retiring what was there
+    pub fn replace<T: 'static>(&mut self, slot: &mut T, value: T) {
+        self.retire(std::mem::replace(slot, value));
+    }
+
+    pub fn len(&self) -> usize {
+        self.retired.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.retired.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use super::*;
+
+    // records its name when dropped
+    struct Tracked(&'static str, Rc<RefCell<Vec<&'static str>>>);
+
+    impl Drop for Tracked {
+        fn drop(&mut self) {
+            self.1.borrow_mut().push(self.0);
+        }
+    }
+
+    fn collect(queue: &mut DeletionQueue, signalled: &[bool]) {
+        queue.collect(|frame| Ok(signalled[frame])).unwrap();
+    }
+
+    #[test]
+    fn retired_resources_wait_for_their_submission() {
+        let dropped = Rc::new(RefCell::new(Vec::new()));
+        let mut queue = DeletionQueue::new(2);
+
+        // nothing has been submitted yet, so nothing can be using it
+        queue.retire(Tracked("unsubmitted", dropped.clone()));
+        collect(&mut queue, &[false, false]);
+        assert_eq!(*dropped.borrow(), ["unsubmitted"]);
+
+        queue.submitted(0);
+        queue.retire(Tracked("after frame 0", dropped.clone()));
+        collect(&mut queue, &[false, false]);
+        assert_eq!(queue.len(), 1);
+
+        queue.submitted(1);
+        queue.retire(Tracked("after frame 1", dropped.clone()));
+
+        // frame 1 is still running, which only holds back what was retired after it
+        collect(&mut queue, &[true, false]);
+        assert_eq!(*dropped.borrow(), ["unsubmitted", "after frame 0"]);
+
+        collect(&mut queue, &[true, true]);
+        assert_eq!(*dropped.borrow(), ["unsubmitted", "after frame 0", "after frame 1"]);
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn pending_submissions_hold_back_later_ones() {
+        let dropped = Rc::new(RefCell::new(Vec::new()));
+        let mut queue = DeletionQueue::new(2);
+
+        queue.submitted(0);
+        queue.retire(Tracked("first", dropped.clone()));
+        queue.submitted(1);
+        queue.retire(Tracked("second", dropped.clone()));
+
+        // frame 1 finishing says nothing about frame 0, which was submitted earlier
+        collect(&mut queue, &[false, true]);
+        assert!(dropped.borrow().is_empty());
+
+        // the frame 0 fence is reused by the next submission
+        queue.submitted(0);
+        queue.retire(Tracked("third", dropped.clone()));
+        collect(&mut queue, &[false, true]);
+        assert_eq!(*dropped.borrow(), ["first", "second"]);
+
+        collect(&mut queue, &[true, true]);
+        assert_eq!(*dropped.borrow(), ["first", "second", "third"]);
+    }
+
+    #[test]
+    fn fence_errors_keep_everything() {
+        let mut queue = DeletionQueue::new(1);
+        queue.submitted(0);
+        queue.retire(0u32);
+
+        assert!(queue.collect(|_| Err(anyhow::anyhow!("device lost"))).is_err());
+        assert_eq!(queue.len(), 1);
+    }
+
+    #[test]
+    fn replace_retires_the_old_value() {
+        let dropped = Rc::new(RefCell::new(Vec::new()));
+        let mut queue = DeletionQueue::new(1);
+        let mut slot = Tracked("old", dropped.clone());
+
+        queue.submitted(0);
+        queue.replace(&mut slot, Tracked("new", dropped.clone()));
+        assert_eq!(slot.0, "new");
+
+        collect(&mut queue, &[false]);
+        assert!(dropped.borrow().is_empty());
+
+        collect(&mut queue, &[true]);
+        assert_eq!(*dropped.borrow(), ["old"]);
+    }
+}
@@ -0,0 +1,192 @@
+use ash::{vk, Device};
+
+use anyhow::{Context, Result};
+
+use log::*;
+
+use super::owned::Owned;
+
+// sets in the first pool, each new pool doubles this up to MAX_SETS_PER_POOL
+const INITIAL_SETS_PER_POOL: u32 = 16;
+const MAX_SETS_PER_POOL: u32 = 4096;
+
+// descriptors of each type reserved per set in a pool
+pub const DEFAULT_POOL_RATIOS: &[(vk::DescriptorType, f32)] = &[
+    (vk::DescriptorType::UNIFORM_BUFFER, 2.0),
+    (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 2.0),
+    (vk::DescriptorType::SAMPLED_IMAGE, 2.0),
+    (vk::DescriptorType::SAMPLER, 2.0),
+    (vk::DescriptorType::STORAGE_BUFFER, 1.0),
+];
+
+// hands out descriptor sets, creating a bigger pool whenever the current one runs out
+pub struct DescriptorAllocator {
+    ratios: Vec<(vk::DescriptorType, f32)>,
+    sets_per_pool: u32,
+    // pools that have run out, kept around until reset or dropped
+    full_pools: Vec<Owned<vk::DescriptorPool>>,
+    // pools that have been reset and can be reused
+    ready_pools: Vec<Owned<vk::DescriptorPool>>,
+    current: Option<Owned<vk::DescriptorPool>>,
+}
+
+impl DescriptorAllocator {
+    pub fn new(ratios: &[(vk::DescriptorType, f32)]) -> Self {
+        Self {
+            ratios: ratios.to_vec(),
+            sets_per_pool: INITIAL_SETS_PER_POOL,
+            full_pools: vec![],
+            ready_pools: vec![],
+            current: None,
+        }
+    }
+
+    /// Allocates a set with `layout`, starting a new pool when the current one is full.
+    ///
+    /// # Safety
+    /// `layout` must have been made on `device`, which has to be the same device for every call.
+    pub unsafe fn allocate(&mut self, device: &Device, layout: vk::DescriptorSetLayout) -> Result<vk::DescriptorSet> {
+        let pool = match &self.current {
+            Some(pool) => **pool,
+            None => self.next_pool(device)?,
+        };
+
+        match allocate_set(device, pool, layout) {
+            Ok(set) => return Ok(set),
+            Err(vk::Result::ERROR_OUT_OF_POOL_MEMORY | vk::Result::ERROR_FRAGMENTED_POOL) => {},
+            Err(e) => return Err(e).context("Failed to allocate descriptor set."),
+        }
+
+        // the pool is exhausted, retry once with a fresh one
+        self.full_pools.extend(self.current.take());
+        let pool = self.next_pool(device)?;
+
+        allocate_set(device, pool, layout)
+            .context("Failed to allocate descriptor set from a new pool.")
+    }
+
+    /// Frees every set handed out so far, keeping the pools for reuse.
+    ///
+    /// # Safety
+    /// No set allocated so far may still be used by a pending command buffer, or be used again.
+    pub unsafe fn reset(&mut self, device: &Device) -> Result<()> {
+        for pool in self.full_pools.drain(..).chain(self.current.take()) {
+            device.reset_descriptor_pool(*pool, vk::DescriptorPoolResetFlags::empty())?;
+            self.ready_pools.push(pool);
+        }
+
+        Ok(())
+    }
+
+    unsafe fn next_pool(&mut self, device: &Device) -> Result<vk::DescriptorPool> {
+        let pool = match self.ready_pools.pop() {
+            Some(pool) => pool,
+            None => {
+                let pool = Owned::new(device, create_pool(device, self.sets_per_pool, &self.ratios)?);
+                debug!("Created descriptor pool for {} sets.", self.sets_per_pool);
+                self.sets_per_pool = (self.sets_per_pool * 2).min(MAX_SETS_PER_POOL);
+                pool
+            },
+        };
+
+        let handle = *pool;
+        self.current = Some(pool);
+
+        Ok(handle)
+    }
+}
+
+unsafe fn create_pool(
+    device: &Device,
+    max_sets: u32,
+    ratios: &[(vk::DescriptorType, f32)],
+) -> Result<vk::DescriptorPool> {
+    let pool_sizes = ratios
+        .iter()
+        .map(|(ty, ratio)| vk::DescriptorPoolSize::default()
+            .ty(*ty)
+            .descriptor_count(((max_sets as f32 * ratio) as u32).max(1)))
+        .collect::<Vec<_>>();
+
+    let pool_info = vk::DescriptorPoolCreateInfo::default()
+        .max_sets(max_sets)
+        .pool_sizes(&pool_sizes);
+
+    Ok(device.create_descriptor_pool(&pool_info, None)?)
+}
+
+unsafe fn allocate_set(
+    device: &Device,
+    pool: vk::DescriptorPool,
+    layout: vk::DescriptorSetLayout,
+) -> Result<vk::DescriptorSet, vk::Result> {
+    let layouts = &[layout];
+    let allocate_info = vk::DescriptorSetAllocateInfo::default()
+        .descriptor_pool(pool)
+        .set_layouts(layouts);
+
+    Ok(device.allocate_descriptor_sets(&allocate_info)?[0])
+}
+
+pub fn create_descriptor_set_layout(
+    device: &Device,
+    bindings: &[vk::DescriptorSetLayoutBinding],
+) -> Result<Owned<vk::DescriptorSetLayout>> {
+    let layout_info = vk::DescriptorSetLayoutCreateInfo::default()
+        .bindings(bindings);
+
+    unsafe { Ok(Owned::new(device, device.create_descriptor_set_layout(&layout_info, None)?)) }
+}
+
+/// Points a uniform buffer binding of a set at the first `range` bytes of a buffer.
+///
+/// # Safety
+/// `set` must not be in use by the gpu, and `binding` must be a uniform buffer in its layout.
+pub unsafe fn write_uniform_buffer(
+    device: &Device,
+    set: vk::DescriptorSet,
+    binding: u32,
+    buffer: vk::Buffer,
+    range: vk::DeviceSize,
+) {
+    let buffer_infos = &[
+        vk::DescriptorBufferInfo::default()
+            .buffer(buffer)
+            .offset(0)
+            .range(range)
+    ];
+
+    let write = vk::WriteDescriptorSet::default()
+        .dst_set(set)
+        .dst_binding(binding)
+        .dst_array_element(0)
+        .descriptor_type(vk::DescriptorType::UNIFORM_BUFFER)
+        .buffer_info(buffer_infos);
+
+    device.update_descriptor_sets(&[write], &[]);
+}
+
+/// Points an image binding of a set at `image_info`. Sampled images, samplers and combined image
+/// samplers each read the matching fields of it.
+///
+/// # Safety
+/// `set` must not be in use by the gpu, and `descriptor_type` must be the type of `binding` in its
+/// layout.
+pub unsafe fn write_image(
+    device: &Device,
+    set: vk::DescriptorSet,
+    binding: u32,
+    descriptor_type: vk::DescriptorType,
+    image_info: vk::DescriptorImageInfo,
+) {
+    let image_infos = &[image_info];
+
+    let write = vk::WriteDescriptorSet::default()
+        .dst_set(set)
+        .dst_binding(binding)
+        .dst_array_element(0)
+        .descriptor_type(descriptor_type)
+        .image_info(image_infos);
+
+    device.update_descriptor_sets(&[write], &[]);
+}
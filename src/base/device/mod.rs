@@ -0,0 +1,340 @@
+use std::{env::VarError, ffi::CStr, fmt};
+
+use ash::{vk, Instance};
+
+use anyhow::{anyhow, Result};
+
+use log::*;
+
+use crate::util::constants::*;
+use crate::util::string_from_utf8;
+
+use super::data;
+
+// picks a specific physical device instead of the highest scoring one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceSelector {
+    Index(usize),
+    Name(String),
+    Uuid([u8; vk::UUID_SIZE]),
+}
+
+impl DeviceSelector {
+    // plain numbers are indices, 32 hex digits (dashes optional) are UUIDs,
+    // anything else is matched as a case-insensitive name substring
+    pub fn parse(selector: &str) -> Result<Self> {
+        let selector = selector.trim();
+
+        if selector.is_empty() {
+            return Err(anyhow!("Empty device selector."));
+        }
+
+        if let Ok(index) = selector.parse() {
+            return Ok(Self::Index(index));
+        }
+
+        let hex = selector.replace('-', "");
+        if hex.len() == vk::UUID_SIZE * 2 && hex.chars().all(|c| c.is_ascii_hexdigit()) {
+            let mut uuid = [0; vk::UUID_SIZE];
+            for (i, byte) in uuid.iter_mut().enumerate() {
+                *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16)?;
+            }
+
+            return Ok(Self::Uuid(uuid));
+        }
+
+        Ok(Self::Name(selector.to_lowercase()))
+    }
+
+    pub fn from_env() -> Result<Option<Self>> {
+        match std::env::var(DEVICE_ENV_VAR) {
+            Ok(selector) => Ok(Some(Self::parse(&selector)?)),
+            Err(VarError::NotPresent) => Ok(None),
+            Err(VarError::NotUnicode(selector)) => {
+                warn!("Ignoring {}, {:?} is not valid unicode.", DEVICE_ENV_VAR, selector);
+                Ok(None)
+            },
+        }
+    }
+
+    pub fn matches(&self, candidate: &DeviceCandidate) -> bool {
+        match self {
+            Self::Index(index) => candidate.index == *index,
+            Self::Name(name) => candidate.name.to_lowercase().contains(name),
+            Self::Uuid(uuid) => candidate.uuid.as_ref() == Some(uuid),
+        }
+    }
+}
+
+impl fmt::Display for DeviceSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Index(index) => write!(f, "index {}", index),
+            Self::Name(name) => write!(f, "name containing \"{}\"", name),
+            Self::Uuid(uuid) => write!(f, "UUID {}", format_uuid(uuid)),
+        }
+    }
+}
+
+// everything we know about a physical device while choosing one
+pub struct DeviceCandidate {
+    pub index: usize,
+    pub device: vk::PhysicalDevice,
+    pub name: String,
+    pub device_type: vk::PhysicalDeviceType,
+    pub uuid: Option<[u8; vk::UUID_SIZE]>,
+    pub device_local_memory: vk::DeviceSize,
+    pub features: vk::PhysicalDeviceFeatures,
+    pub swapchain_support: Option<data::SwapchainSupport>,
+    // empty when the device is usable
+    pub rejections: Vec<String>,
+}
+
+impl DeviceCandidate {
+    /// Gathers what selection needs to know about a physical device, recording why it can't be
+    /// used, if it can't. A query that fails only rejects this device, so the others can still be
+    /// chosen.
+    ///
+    /// # Safety
+    /// `device` must have come from `instance`, and the surface, if any, from the same instance.
+    pub unsafe fn get(
+        instance: &Instance,
+        surface_data: Option<&data::SurfaceData>,
+        device_extension_names: &[&CStr],
+        index: usize,
+        device: vk::PhysicalDevice,
+    ) -> Self {
+        let properties = instance.get_physical_device_properties(device);
+        let features = instance.get_physical_device_features(device);
+        let memory_properties = instance.get_physical_device_memory_properties(device);
+
+        // the device UUID needs vulkan 1.1
+        let uuid = if properties.api_version >= vk::API_VERSION_1_1 {
+            let mut id_properties = vk::PhysicalDeviceIDProperties::default();
+            let mut properties2 = vk::PhysicalDeviceProperties2::default()
+                .push_next(&mut id_properties);
+            instance.get_physical_device_properties2(device, &mut properties2);
+            Some(id_properties.device_uuid)
+        } else {
+            None
+        };
+
+        let device_local_memory = memory_properties.memory_heaps[..memory_properties.memory_heap_count as usize]
+            .iter()
+            .filter(|h| h.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL))
+            .map(|h| h.size)
+            .sum();
+
+        let mut rejections = vec![];
+
+        // check for required queue families
+        if let Err(e) = data::QueueFamilyIndices::get(instance, surface_data, device) {
+            rejections.push(e.to_string());
+        }
+
+        // check for needed extensions
+        let has_extensions = match instance.enumerate_device_extension_properties(device) {
+            Ok(extensions) => {
+                let missing = device_extension_names
+                    .iter()
+                    .filter(|e| !extensions.iter().any(|x| x.extension_name_as_c_str() == Ok(**e)))
+                    .map(|e| e.to_string_lossy())
+                    .collect::<Vec<_>>();
+
+                if !missing.is_empty() {
+                    rejections.push(format!("Missing device extensions: {}.", missing.join(", ")));
+                }
+
+                missing.is_empty()
+            },
+            Err(e) => {
+                rejections.push(format!("Failed to query device extensions ({:?}).", e));
+                false
+            },
+        };
+
+        // swapchain support only matters when there is a surface to present to
+        let mut swapchain_support = None;
+        if let (Some(surface_data), true) = (surface_data, has_extensions) {
+            match data::SwapchainSupport::get(surface_data, device) {
+                Ok(support) => {
+                    if support.formats.is_empty() {
+                        rejections.push("No surface formats.".to_string());
+                    }
+
+                    if support.present_modes.is_empty() {
+                        rejections.push("No present modes.".to_string());
+                    }
+
+                    swapchain_support = Some(support);
+                },
+                Err(e) => rejections.push(format!("Failed to query surface support: {:#}", e)),
+            }
+        }
+
+        Self {
+            index,
+            device,
+            name: string_from_utf8(&properties.device_name),
+            device_type: properties.device_type,
+            uuid,
+            device_local_memory,
+            features,
+            swapchain_support,
+            rejections,
+        }
+    }
+
+    pub fn is_suitable(&self) -> bool {
+        self.rejections.is_empty()
+    }
+
+    // device type dominates, then the amount of device-local memory, then optional features
+    pub fn score(&self) -> u64 {
+        let type_score: u64 = match self.device_type {
+            vk::PhysicalDeviceType::DISCRETE_GPU => 4,
+            vk::PhysicalDeviceType::INTEGRATED_GPU => 3,
+            vk::PhysicalDeviceType::VIRTUAL_GPU => 2,
+            vk::PhysicalDeviceType::CPU => 1,
+            _ => 0,
+        };
+
+        let memory_score = (self.device_local_memory / (1024 * 1024)).min(999_999);
+
+        let features = [
+            self.features.sampler_anisotropy,
+            self.features.sample_rate_shading,
+            self.features.fill_mode_non_solid,
+            self.features.wide_lines,
+        ];
+        let feature_score = features.iter().filter(|f| **f == vk::TRUE).count() as u64;
+
+        type_score * 100_000_000 + memory_score * 100 + feature_score
+    }
+}
+
+impl fmt::Display for DeviceCandidate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "[{}] {} ({:?}, {} MiB device-local",
+            self.index,
+            self.name,
+            self.device_type,
+            self.device_local_memory / (1024 * 1024),
+        )?;
+
+        if let Some(uuid) = &self.uuid {
+            write!(f, ", UUID {}", format_uuid(uuid))?;
+        }
+
+        write!(f, ")")
+    }
+}
+
+pub fn format_uuid(uuid: &[u8; vk::UUID_SIZE]) -> String {
+    uuid.iter()
+        .enumerate()
+        .map(|(i, b)| match i {
+            4 | 6 | 8 | 10 => format!("-{:02x}", b),
+            _ => format!("{:02x}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn candidate(index: usize, name: &str, device_type: vk::PhysicalDeviceType, memory_mib: vk::DeviceSize) -> DeviceCandidate {
+        DeviceCandidate {
+            index,
+            device: vk::PhysicalDevice::null(),
+            name: name.to_string(),
+            device_type,
+            uuid: None,
+            device_local_memory: memory_mib * 1024 * 1024,
+            features: vk::PhysicalDeviceFeatures::default(),
+            swapchain_support: None,
+            rejections: vec![],
+        }
+    }
+
+    #[test]
+    fn numbers_are_indices() {
+        assert_eq!(DeviceSelector::parse("0").unwrap(), DeviceSelector::Index(0));
+        assert_eq!(DeviceSelector::parse(" 12 ").unwrap(), DeviceSelector::Index(12));
+
+        // not an index, so it is taken as a name
+        assert_eq!(DeviceSelector::parse("-1").unwrap(), DeviceSelector::Name("-1".to_string()));
+    }
+
+    #[test]
+    fn uuids_are_parsed_with_or_without_dashes() {
+        let uuid = [0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef, 0xfe, 0xdc, 0xba, 0x98, 0x76, 0x54, 0x32, 0x10];
+
+        assert_eq!(DeviceSelector::parse("0123456789abcdeffedcba9876543210").unwrap(), DeviceSelector::Uuid(uuid));
+        assert_eq!(DeviceSelector::parse("01234567-89AB-CDEF-FEDC-BA9876543210").unwrap(), DeviceSelector::Uuid(uuid));
+        assert_eq!(DeviceSelector::parse(&format_uuid(&uuid)).unwrap(), DeviceSelector::Uuid(uuid));
+
+        // one digit short is a name
+        assert!(matches!(DeviceSelector::parse("0123456789abcdeffedcba987654321").unwrap(), DeviceSelector::Name(_)));
+    }
+
+    #[test]
+    fn names_match_case_insensitive_substrings() {
+        let selector = DeviceSelector::parse(" GeForce ").unwrap();
+        assert_eq!(selector, DeviceSelector::Name("geforce".to_string()));
+
+        assert!(selector.matches(&candidate(0, "NVIDIA GeForce RTX 3080", vk::PhysicalDeviceType::DISCRETE_GPU, 0)));
+        assert!(selector.matches(&candidate(0, "nvidia geforce gtx 1060", vk::PhysicalDeviceType::DISCRETE_GPU, 0)));
+        assert!(!selector.matches(&candidate(0, "AMD Radeon RX 6800", vk::PhysicalDeviceType::DISCRETE_GPU, 0)));
+    }
+
+    #[test]
+    fn indices_and_uuids_match_exactly() {
+        let mut device = candidate(1, "llvmpipe", vk::PhysicalDeviceType::CPU, 0);
+
+        assert!(DeviceSelector::Index(1).matches(&device));
+        assert!(!DeviceSelector::Index(0).matches(&device));
+
+        // devices without a uuid never match one
+        assert!(!DeviceSelector::Uuid([1; vk::UUID_SIZE]).matches(&device));
+
+        device.uuid = Some([1; vk::UUID_SIZE]);
+        assert!(DeviceSelector::Uuid([1; vk::UUID_SIZE]).matches(&device));
+        assert!(!DeviceSelector::Uuid([2; vk::UUID_SIZE]).matches(&device));
+    }
+
+    #[test]
+    fn empty_selectors_are_rejected() {
+        assert!(DeviceSelector::parse("").is_err());
+        assert!(DeviceSelector::parse("   ").is_err());
+    }
+
+    #[test]
+    fn device_type_outranks_memory() {
+        let discrete = candidate(0, "discrete", vk::PhysicalDeviceType::DISCRETE_GPU, 2 * 1024);
+        let integrated = candidate(1, "integrated", vk::PhysicalDeviceType::INTEGRATED_GPU, 64 * 1024);
+        let cpu = candidate(2, "cpu", vk::PhysicalDeviceType::CPU, 64 * 1024);
+
+        assert!(discrete.score() > integrated.score());
+        assert!(integrated.score() > cpu.score());
+
+        // the memory score is capped so it can never make up for the device type
+        let huge = candidate(3, "huge", vk::PhysicalDeviceType::INTEGRATED_GPU, vk::DeviceSize::MAX / (1024 * 1024));
+        assert!(discrete.score() > huge.score());
+    }
+
+    #[test]
+    fn memory_then_features_break_ties() {
+        let small = candidate(0, "small", vk::PhysicalDeviceType::DISCRETE_GPU, 4 * 1024);
+        let mut large = candidate(1, "large", vk::PhysicalDeviceType::DISCRETE_GPU, 8 * 1024);
+        assert!(large.score() > small.score());
+
+        let plain = candidate(2, "plain", vk::PhysicalDeviceType::DISCRETE_GPU, 8 * 1024);
+        large.features.sampler_anisotropy = vk::TRUE;
+        large.features.wide_lines = vk::TRUE;
+        assert_eq!(large.score(), plain.score() + 2);
+    }
+}
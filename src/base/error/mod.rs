@@ -0,0 +1,108 @@
+use std::fmt;
+
+use ash::vk;
+
+// what App's public functions return, so callers can tell a lost device from a bad shader
+// without picking apart messages. anything without a variant of its own ends up in Other
+#[derive(Debug)]
+pub enum Error {
+    // every physical device was rejected or none matched the selection, with the reasons
+    NoSuitableDevice(String),
+    // name is None when the driver rejected creation without saying which one was missing
+    MissingLayer { name: Option<String>, result: vk::Result },
+    MissingExtension { name: Option<String>, result: vk::Result },
+    SurfaceLost(vk::Result),
+    // the swapchain no longer matches the surface and couldn't be recreated
+    OutOfDateSwapchain(vk::Result),
+    DeviceLost(vk::Result),
+    // host or device memory, see the result for which
+    OutOfMemory(vk::Result),
+    // a scene shader failed to compile, reflect or match the pipeline it is used in
+    Shader(anyhow::Error),
+    Other(anyhow::Error),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl Error {
+    // the vulkan result behind the error, if it came from a vulkan call
+    pub fn vk_result(&self) -> Option<vk::Result> {
+        match self {
+            Self::MissingLayer { result, .. }
+            | Self::MissingExtension { result, .. }
+            | Self::SurfaceLost(result)
+            | Self::OutOfDateSwapchain(result)
+            | Self::DeviceLost(result)
+            | Self::OutOfMemory(result) => Some(*result),
+            Self::Shader(e) | Self::Other(e) => e.downcast_ref::<vk::Result>().copied(),
+            Self::NoSuitableDevice(_) => None,
+        }
+    }
+
+    pub fn is_device_lost(&self) -> bool {
+        matches!(self, Self::DeviceLost(_))
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoSuitableDevice(reason) => write!(f, "No suitable device: {}", reason),
+            Self::MissingLayer { name: Some(name), .. } => write!(f, "Missing instance layer {}.", name),
+            Self::MissingLayer { name: None, result } => write!(f, "Missing instance layer ({:?}).", result),
+            Self::MissingExtension { name: Some(name), .. } => write!(f, "Missing extension {}.", name),
+            Self::MissingExtension { name: None, result } => write!(f, "Missing extension ({:?}).", result),
+            Self::SurfaceLost(result) => write!(f, "Surface lost ({:?}).", result),
+            Self::OutOfDateSwapchain(result) => write!(f, "Swapchain out of date ({:?}).", result),
+            Self::DeviceLost(result) => write!(f, "Device lost ({:?}).", result),
+            Self::OutOfMemory(result) => write!(f, "Out of memory ({:?}).", result),
+            Self::Shader(e) => write!(f, "Shader error: {:#}", e),
+            Self::Other(e) => write!(f, "{:#}", e),
+        }
+    }
+}
+
+// the anyhow chains are already part of the message, so only vulkan results are a source
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::MissingLayer { result, .. }
+            | Self::MissingExtension { result, .. }
+            | Self::SurfaceLost(result)
+            | Self::OutOfDateSwapchain(result)
+            | Self::DeviceLost(result)
+            | Self::OutOfMemory(result) => Some(result),
+            _ => None,
+        }
+    }
+}
+
+impl From<vk::Result> for Error {
+    fn from(result: vk::Result) -> Self {
+        match result {
+            vk::Result::ERROR_LAYER_NOT_PRESENT => Self::MissingLayer { name: None, result },
+            vk::Result::ERROR_EXTENSION_NOT_PRESENT => Self::MissingExtension { name: None, result },
+            vk::Result::ERROR_SURFACE_LOST_KHR => Self::SurfaceLost(result),
+            vk::Result::ERROR_OUT_OF_DATE_KHR => Self::OutOfDateSwapchain(result),
+            vk::Result::ERROR_DEVICE_LOST => Self::DeviceLost(result),
+            vk::Result::ERROR_OUT_OF_HOST_MEMORY | vk::Result::ERROR_OUT_OF_DEVICE_MEMORY => Self::OutOfMemory(result),
+            _ => Self::Other(result.into()),
+        }
+    }
+}
+
+// the internals still use anyhow, so the typed error is recovered at the public boundary: either
+// one was raised directly, or a vulkan result with a variant of its own is somewhere in the chain
+impl From<anyhow::Error> for Error {
+    fn from(e: anyhow::Error) -> Self {
+        let e = match e.downcast::<Error>() {
+            Ok(error) => return error,
+            Err(e) => e,
+        };
+
+        match e.downcast_ref::<vk::Result>().copied().map(Error::from) {
+            Some(Self::Other(_)) | None => Self::Other(e),
+            Some(error) => error,
+        }
+    }
+}
@@ -0,0 +1,216 @@
+use ash::{vk, Device};
+
+use anyhow::{anyhow, Result};
+
+use crate::util::spirv::ShaderReflection;
+
+use super::descriptor;
+use super::owned::Owned;
+
+// descriptor set layouts and push constant ranges merged from the reflection of every stage
+// of a pipeline, so they never have to be written by hand to match the shaders
+pub struct ReflectedLayout {
+    // bindings of each set, indexed by set number. sets no shader uses are left empty
+    pub sets: Vec<Vec<vk::DescriptorSetLayoutBinding<'static>>>,
+    pub push_constant_ranges: Vec<vk::PushConstantRange>,
+    // one per entry of sets
+    pub set_layouts: Vec<Owned<vk::DescriptorSetLayout>>,
+}
+
+impl ReflectedLayout {
+    pub fn create(device: &Device, stages: &[(vk::ShaderStageFlags, ShaderReflection)]) -> Result<Self> {
+        let (sets, push_constant_ranges) = merge_stages(stages)?;
+
+        let set_layouts = sets
+            .iter()
+            .map(|bindings| descriptor::create_descriptor_set_layout(device, bindings))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(
+            Self {
+                sets,
+                push_constant_ranges,
+                set_layouts,
+            }
+        )
+    }
+
+    // for pipeline layouts, in set order
+    pub fn set_layout_handles(&self) -> Vec<vk::DescriptorSetLayout> {
+        self.set_layouts.iter().map(|l| **l).collect()
+    }
+
+    // checks a stage's shader against this layout, e.g. one that was edited after it was made
+    pub fn check(&self, stage: vk::ShaderStageFlags, reflection: &ShaderReflection) -> Result<()> {
+        let set_bindings = self.sets.iter().map(Vec::as_slice).collect::<Vec<_>>();
+
+        reflection.check_layout(stage, &set_bindings, &self.push_constant_ranges)
+    }
+}
+
+type Sets = Vec<Vec<vk::DescriptorSetLayoutBinding<'static>>>;
+
+fn merge_stages(stages: &[(vk::ShaderStageFlags, ShaderReflection)]) -> Result<(Sets, Vec<vk::PushConstantRange>)> {
+    let mut sets: Sets = vec![];
+
+    for (stage, reflection) in stages {
+        for binding in &reflection.descriptor_bindings {
+            if binding.count == 0 {
+                return Err(anyhow!(
+                    "{:?} shader declares a runtime sized array at set {} binding {}, which needs descriptor indexing",
+                    stage, binding.set, binding.binding,
+                ));
+            }
+
+            let set = binding.set as usize;
+            if sets.len() <= set {
+                sets.resize(set + 1, vec![]);
+            }
+
+            match sets[set].iter_mut().find(|b| b.binding == binding.binding) {
+                Some(existing) if existing.descriptor_type != binding.descriptor_type => {
+                    return Err(anyhow!(
+                        "Set {} binding {} is a {:?} in the {:?} shader but a {:?} in the {:?} shader",
+                        binding.set, binding.binding, existing.descriptor_type, existing.stage_flags, binding.descriptor_type, stage,
+                    ));
+                },
+                Some(existing) => {
+                    existing.stage_flags |= *stage;
+                    existing.descriptor_count = existing.descriptor_count.max(binding.count);
+                },
+                None => {
+                    sets[set].push(
+                        vk::DescriptorSetLayoutBinding::default()
+                            .binding(binding.binding)
+                            .descriptor_type(binding.descriptor_type)
+                            .descriptor_count(binding.count)
+                            .stage_flags(*stage)
+                    );
+                },
+            }
+        }
+    }
+
+    sets.iter_mut().for_each(|s| s.sort_by_key(|b| b.binding));
+
+    // a single range visible to every stage with push constants, covering the biggest block
+    let push_constant_stages = stages
+        .iter()
+        .filter(|(_, r)| r.push_constant_size.is_some())
+        .fold(vk::ShaderStageFlags::empty(), |flags, (stage, _)| flags | *stage);
+    let push_constant_size = stages
+        .iter()
+        .filter_map(|(_, r)| r.push_constant_size)
+        .max()
+        .unwrap_or(0);
+
+    let push_constant_ranges = match push_constant_size {
+        0 => vec![],
+        size => vec![
+            vk::PushConstantRange::default()
+                .stage_flags(push_constant_stages)
+                .offset(0)
+                .size(size.next_multiple_of(4))
+        ],
+    };
+
+    Ok((sets, push_constant_ranges))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::util::spirv::DescriptorBinding;
+
+    const VERTEX: vk::ShaderStageFlags = vk::ShaderStageFlags::VERTEX;
+    const FRAGMENT: vk::ShaderStageFlags = vk::ShaderStageFlags::FRAGMENT;
+
+    fn binding(set: u32, binding: u32, descriptor_type: vk::DescriptorType, count: u32) -> DescriptorBinding {
+        DescriptorBinding {
+            set,
+            binding,
+            descriptor_type,
+            count,
+            name: None,
+        }
+    }
+
+    fn reflection(descriptor_bindings: Vec<DescriptorBinding>, push_constant_size: Option<u32>) -> ShaderReflection {
+        ShaderReflection {
+            version: (1, 0),
+            entry_points: vec![],
+            descriptor_bindings,
+            push_constant_size,
+        }
+    }
+
+    #[test]
+    fn shared_bindings_merge_their_stages() {
+        let stages = [
+            (VERTEX, reflection(vec![binding(0, 0, vk::DescriptorType::UNIFORM_BUFFER, 1)], None)),
+            (FRAGMENT, reflection(vec![
+                binding(0, 2, vk::DescriptorType::SAMPLER, 1),
+                binding(0, 0, vk::DescriptorType::UNIFORM_BUFFER, 1),
+                binding(0, 1, vk::DescriptorType::SAMPLED_IMAGE, 4),
+            ], None)),
+        ];
+
+        let (sets, push_constant_ranges) = merge_stages(&stages).unwrap();
+        assert_eq!(sets.len(), 1);
+        assert!(push_constant_ranges.is_empty());
+
+        // sorted by binding, each visible to every stage that declared it
+        let bindings = sets[0].iter().map(|b| (b.binding, b.descriptor_type, b.descriptor_count, b.stage_flags)).collect::<Vec<_>>();
+        assert_eq!(bindings, vec![
+            (0, vk::DescriptorType::UNIFORM_BUFFER, 1, VERTEX | FRAGMENT),
+            (1, vk::DescriptorType::SAMPLED_IMAGE, 4, FRAGMENT),
+            (2, vk::DescriptorType::SAMPLER, 1, FRAGMENT),
+        ]);
+    }
+
+    #[test]
+    fn unused_sets_are_left_empty() {
+        let stages = [(FRAGMENT, reflection(vec![binding(2, 0, vk::DescriptorType::STORAGE_BUFFER, 1)], None))];
+
+        let (sets, _) = merge_stages(&stages).unwrap();
+        assert_eq!(sets.iter().map(Vec::len).collect::<Vec<_>>(), [0, 0, 1]);
+    }
+
+    #[test]
+    fn conflicting_descriptor_types_are_rejected() {
+        let stages = [
+            (VERTEX, reflection(vec![binding(0, 1, vk::DescriptorType::UNIFORM_BUFFER, 1)], None)),
+            (FRAGMENT, reflection(vec![binding(0, 1, vk::DescriptorType::STORAGE_BUFFER, 1)], None)),
+        ];
+
+        let error = merge_stages(&stages).unwrap_err().to_string();
+        assert!(error.contains("Set 0 binding 1"), "unexpected error {}", error);
+    }
+
+    #[test]
+    fn runtime_sized_arrays_are_rejected() {
+        let stages = [(FRAGMENT, reflection(vec![binding(0, 0, vk::DescriptorType::SAMPLED_IMAGE, 0)], None))];
+
+        assert!(merge_stages(&stages).is_err());
+    }
+
+    #[test]
+    fn push_constants_share_one_range() {
+        let stages = [
+            (VERTEX, reflection(vec![], Some(64))),
+            (FRAGMENT, reflection(vec![], Some(18))),
+            (vk::ShaderStageFlags::GEOMETRY, reflection(vec![], None)),
+        ];
+
+        let (_, push_constant_ranges) = merge_stages(&stages).unwrap();
+        assert_eq!(push_constant_ranges.len(), 1);
+        assert_eq!(push_constant_ranges[0].stage_flags, VERTEX | FRAGMENT);
+        assert_eq!((push_constant_ranges[0].offset, push_constant_ranges[0].size), (0, 64));
+
+        // the size is rounded up to a multiple of 4, as vulkan requires
+        let stages = [(FRAGMENT, reflection(vec![], Some(18)))];
+        let (_, push_constant_ranges) = merge_stages(&stages).unwrap();
+        assert_eq!(push_constant_ranges[0].size, 20);
+    }
+}
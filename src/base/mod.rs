@@ -14,202 +14,816 @@ use ash::{
 use winit::raw_window_handle::{HasDisplayHandle, HasWindowHandle};
 
 use crate::util::constants::*;
+use crate::util::spirv::EntryPoint;
 use crate::util::Bytecode;
 
-use anyhow::{anyhow, Result};
+use anyhow::{anyhow, Context, Result};
 
 use log::*;
 
-use self::data::{PipelineData, SyncObjects};
-
-mod data;
+use self::data::{AttachmentImage, FrameContext, PipelineData, RenderTarget, UniformBufferObject};
+use self::capture::FrameCapture;
+use self::config::{Config, DepthConfig};
+use self::device::{DeviceCandidate, DeviceSelector};
+use self::error::Error;
+use self::allocator::{Allocator, MemoryUsage};
+use self::buffer::{BufferData, Index, IndexBuffer, Mesh, Vertex, TexturedVertex, VertexBuffer, VertexLayout};
+use self::deletion_queue::DeletionQueue;
+use self::descriptor::DescriptorAllocator;
+use self::texture::Texture;
+use self::record::{RecordContext, Recorder};
+use self::layout::ReflectedLayout;
+use self::pipeline::GraphicsPipelineBuilder;
+use self::pipeline_cache::PipelineCache;
+use self::owned::{Owned, OwnedDevice, OwnedInstance};
+use self::shader::ShaderWatcher;
+
+pub mod data;
+pub mod allocator;
+pub mod buffer;
+pub mod capture;
+pub mod config;
+pub mod deletion_queue;
+pub mod device;
+pub mod descriptor;
+pub mod error;
+pub mod texture;
+pub mod record;
+pub mod layout;
+pub mod owned;
+pub mod pipeline;
+pub mod pipeline_cache;
+pub mod shader;
 
 /* 
  * Main structs
  */
 
-// holds all the top-level important data
+// holds all the top-level important data. fields are dropped in declaration order, so
+// everything is declared before whatever it was created from
 pub struct App {
-    pub entry: Entry,
-    pub window: winit::window::Window,
-    pub instance: Instance,
-    pub debug_data: Option<data::DebugData>,
-    pub surface_data: data::SurfaceData,
-    pub physical_device_data: data::PhysicalDeviceData,
-    pub queue_data: data::QueueData,
-    pub logical_device: Device,
-    pub swapchain_data: data::SwapchainData,
-    pub render_pass: vk::RenderPass,
-    pub pipeline_data: data::PipelineData,
-    pub framebuffers: Vec<vk::Framebuffer>,
-    pub command_pool: vk::CommandPool,
-    pub command_buffers: Vec<vk::CommandBuffer>,
-    pub sync_objects: data::SyncObjects,
+    pub config: Config,
+    // records each frame, the default scene when None
+    pub recorder: Option<Recorder>,
+    // called once the renderer has been rebuilt on a new device
+    pub on_device_reset: Option<DeviceResetHook>,
+    // rebuilds the pipeline when its shaders change on disk, only in debug builds
+    pub shader_watcher: Option<ShaderWatcher>,
+    // last scene shaders that built a working pipeline, starting from the embedded ones. every
+    // rebuild but a hot reload uses these, so a broken edit on disk can't fail a resize
+    pub scene_shaders: Vec<(vk::ShaderStageFlags, Bytecode)>,
+    // written to the current frame's uniform buffer by draw_frame
+    pub uniforms: UniformBufferObject,
+    pub resized: bool,
+    // set while the renderer is being rebuilt after losing the device, and left set if that failed
+    // so the next frame tries again
+    pub device_lost: bool,
+    // makes the next frame fail as if the device was lost, see simulate_device_loss
+    #[cfg(any(test, feature = "test-hooks"))]
+    lose_device: bool,
+    // resources replaced while frames were in flight, dropped once those frames finish
+    pub deletion_queue: DeletionQueue,
+    // the ring of frames in flight, config.frames_in_flight long
+    pub frames: Vec<FrameContext>,
+    // index into frames of the next one to render
     pub frame: usize,
+    // fence of the frame that last rendered to each target image, null until it is first used
+    pub images_in_flight: Vec<vk::Fence>,
+    pub descriptor_allocator: DescriptorAllocator,
+    // sampled in the fragment shader, plain white until replaced with set_texture
+    pub texture: Texture,
+    pub mesh: Mesh<TexturedVertex>,
+    // for one-off uploads and readbacks
+    pub command_pool: Owned<vk::CommandPool>,
+    pub framebuffers: Vec<Owned<vk::Framebuffer>>,
+    pub pipeline_data: data::PipelineData,
+    pub pipeline_cache: PipelineCache,
+    // descriptor set layouts and push constants of the scene shaders
+    pub scene_layout: ReflectedLayout,
+    pub render_pass: Owned<vk::RenderPass>,
+    // multisampled colour resolved into the target, None without msaa
+    pub msaa_color: Option<AttachmentImage>,
+    pub msaa_samples: vk::SampleCountFlags,
+    pub depth: AttachmentImage,
+    pub target: data::RenderTarget,
+    pub allocator: Allocator,
+    pub queue_data: data::QueueData,
+    pub logical_device: OwnedDevice,
+    pub physical_device_data: data::PhysicalDeviceData,
+    pub surface_data: Option<data::SurfaceData>,
+    // None when running headless
+    pub window: Option<winit::window::Window>,
+    pub debug_data: Option<data::DebugData>,
+    pub instance: OwnedInstance,
+    pub entry: Entry,
 }
 
+// everything made from the logical device, in the same drop order as the matching App fields
+struct DeviceObjects {
+    deletion_queue: DeletionQueue,
+    frames: Vec<FrameContext>,
+    images_in_flight: Vec<vk::Fence>,
+    descriptor_allocator: DescriptorAllocator,
+    texture: Texture,
+    mesh: Mesh<TexturedVertex>,
+    command_pool: Owned<vk::CommandPool>,
+    framebuffers: Vec<Owned<vk::Framebuffer>>,
+    pipeline_data: data::PipelineData,
+    pipeline_cache: PipelineCache,
+    scene_layout: ReflectedLayout,
+    render_pass: Owned<vk::RenderPass>,
+    msaa_color: Option<AttachmentImage>,
+    msaa_samples: vk::SampleCountFlags,
+    depth: AttachmentImage,
+    target: data::RenderTarget,
+    allocator: Allocator,
+    queue_data: data::QueueData,
+    logical_device: OwnedDevice,
+    physical_device_data: data::PhysicalDeviceData,
+}
+
+pub type DeviceResetHook = Box<dyn FnMut(&mut App) -> Result<()>>;
+
 impl App {
-    pub fn create(window: winit::window::Window) -> Result<Self> {
+    pub fn create(window: winit::window::Window, config: Config) -> error::Result<Self> {
+        Self::create_for(Some(window), vk::Extent2D::default(), config)
+    }
+
+    // renders into offscreen images instead of a swapchain, no window or display needed
+    pub fn create_headless(width: u32, height: u32, config: Config) -> error::Result<Self> {
+        Self::create_for(None, vk::Extent2D { width, height }, config)
+    }
+
+    fn create_for(window: Option<winit::window::Window>, headless_extent: vk::Extent2D, config: Config) -> error::Result<Self> {
+        if !(1..=MAX_FRAMES_IN_FLIGHT).contains(&config.frames_in_flight) {
+            return Err(anyhow!("Frames in flight must be between 1 and {}, not {}.", MAX_FRAMES_IN_FLIGHT, config.frames_in_flight).into());
+        }
+
         /* entry */
         info!("Creating entry.");
         let entry = Entry::linked();
 
         /* instance */
         info!("Creating instance.");
-        let instance = create_instance(&window, &entry)?;
+        let instance = OwnedInstance::new(create_instance(window.as_ref(), &entry)?);
 
         if VALIDATION_ENABLED {
             info!("Creating debug utils loader and callback.")
         }
-        let debug_data = create_debug_data(&instance, &entry);
+        let debug_data = create_debug_data(&instance, &entry)?;
 
         /* surface */
-        info!("Creating surface.");
-        let surface_data = create_surface(&entry, &instance, &window)?;
+        let surface_data = match &window {
+            Some(window) => {
+                info!("Creating surface.");
+                Some(create_surface(&entry, &instance, window)?)
+            },
+            None => None,
+        };
 
-        /* physical device */
-        info!("Choosing device.");
-        // get required device extension names
-        let device_extension_names = vec![
-            swapchain::NAME,
-            #[cfg(any(target_os = "macos", target_os = "ios"))]
-            ash::khr::portability_subset::NAME,
-        ];
+        let scene_shaders = shader::SCENE_SHADERS
+            .iter()
+            .map(|s| (s.stage, s.embedded.clone()))
+            .collect::<Vec<_>>();
 
-        // get required device extension names as pointers
-        let device_extension_names_raw = device_extension_names.iter().map(|e| e.as_ptr()).collect::<Vec<_>>();
+        let objects = create_device_objects(&instance, window.as_ref(), surface_data.as_ref(), headless_extent, &config, &scene_shaders)?;
 
-        let physical_device_data = choose_device(&instance, &surface_data, &device_extension_names)?;
+        Ok(
+            Self {
+                config,
+                recorder: None,
+                on_device_reset: None,
+                shader_watcher: cfg!(debug_assertions).then(|| ShaderWatcher::new(shader::SCENE_SHADERS)),
+                scene_shaders,
+                uniforms: UniformBufferObject::default(),
+                resized: false,
+                device_lost: false,
+                #[cfg(any(test, feature = "test-hooks"))]
+                lose_device: false,
+                deletion_queue: objects.deletion_queue,
+                frames: objects.frames,
+                frame: 0,
+                images_in_flight: objects.images_in_flight,
+                descriptor_allocator: objects.descriptor_allocator,
+                texture: objects.texture,
+                mesh: objects.mesh,
+                command_pool: objects.command_pool,
+                framebuffers: objects.framebuffers,
+                pipeline_data: objects.pipeline_data,
+                pipeline_cache: objects.pipeline_cache,
+                scene_layout: objects.scene_layout,
+                render_pass: objects.render_pass,
+                msaa_color: objects.msaa_color,
+                msaa_samples: objects.msaa_samples,
+                depth: objects.depth,
+                target: objects.target,
+                allocator: objects.allocator,
+                queue_data: objects.queue_data,
+                logical_device: objects.logical_device,
+                physical_device_data: objects.physical_device_data,
+                surface_data,
+                window,
+                debug_data,
+                instance,
+                entry,
+            }
+        )
+    }
+
+    /// Renders a frame. A lost device is rebuilt rather than returned, skipping the frame.
+    ///
+    /// # Safety
+    /// Anything the caller made from a device that is lost must be dropped, and made again in the
+    /// device reset hook if still needed.
+    pub unsafe fn render_frame(
+        &mut self,
+    ) -> error::Result<()> {
+        // there is no swapchain to rebuild while minimized, so the frame is skipped until there is
+        if self.device_lost {
+            match self.is_minimized() {
+                true => return Ok(()),
+                false => self.recreate_device()?,
+            }
+        }
 
-        let queue_family_indices = unsafe { data::QueueFamilyIndices::get(&instance, &surface_data, physical_device_data.device)? };
-        
-        info!("Creating logical device.");
-        let logical_device = create_logical_device(&instance, &physical_device_data, &queue_family_indices, &device_extension_names_raw)?;
+        self.reload_changed_shaders()?;
 
-        let queue_data = unsafe { data::QueueData::get(queue_family_indices, &logical_device) };
+        match self.draw_frame(false) {
+            Err(e) if e.is_device_lost() => self.recover_device(&e),
+            result => result.map(|_| ()),
+        }
+    }
 
-        info!("Creating swapchain.");
-        let swapchain_data = create_swapchain(&window, &instance, &surface_data, &physical_device_data, &queue_data, &logical_device)?;
+    /// Renders a frame and copies it back to the host as rgba8. A lost device is rebuilt, but the
+    /// error is returned as there is no frame.
+    ///
+    /// # Safety
+    /// If the device is lost, anything the caller made from it must be dropped, and made again in
+    /// the device reset hook if it is still needed.
+    pub unsafe fn capture_frame(
+        &mut self,
+    ) -> error::Result<FrameCapture> {
+        if let Some(swapchain_support) = &self.physical_device_data.swapchain_support {
+            if !swapchain_support.capabilities.supported_usage_flags.contains(vk::ImageUsageFlags::TRANSFER_SRC) {
+                return Err(anyhow!("Swapchain images can't be copied from on this device.").into());
+            }
+        }
 
-        info!("Creating render pass.");
-        let render_pass = create_render_pass(&logical_device, &swapchain_data)?;
+        // nothing to capture until the device can be rebuilt
+        if self.device_lost {
+            match self.is_minimized() {
+                true => self.check_device()?,
+                false => self.recreate_device()?,
+            }
+        }
 
-        info!("Creating pipeline.");
-        let pipeline_data = create_pipeline(&logical_device, &swapchain_data, &render_pass)?;
+        // the device is rebuilt, but there is still no frame to return
+        match self.draw_frame(true) {
+            Ok(frame_capture) => frame_capture.ok_or_else(|| anyhow!("No frame was rendered to capture.").into()),
+            Err(e) if e.is_device_lost() => self.recover_device(&e).and(Err(e)),
+            Err(e) => Err(e),
+        }
+    }
 
-        info!("Creating framebuffers.");
-        let framebuffers = create_framebuffers(&logical_device, &swapchain_data, &render_pass)?;
+    unsafe fn recover_device(&mut self, error: &Error) -> error::Result<()> {
+        let properties = self.instance.get_physical_device_properties(self.physical_device_data.device);
+        let stats = self.allocator.stats();
+
+        error!("{}", error);
+        error!(
+            "Lost device {} (driver {:#x}, vulkan {}.{}.{}) in frame slot {} of {}.",
+            properties.device_name_as_c_str().unwrap_or(c"unknown").to_string_lossy(),
+            properties.driver_version,
+            vk::api_version_major(properties.api_version),
+            vk::api_version_minor(properties.api_version),
+            vk::api_version_patch(properties.api_version),
+            self.frame,
+            self.frames.len(),
+        );
+        error!(
+            "{} allocations using {} of {} bytes in {} blocks, {} retired resources waiting.",
+            stats.allocations,
+            stats.used,
+            stats.reserved,
+            stats.blocks,
+            self.deletion_queue.len(),
+        );
+
+        self.recreate_device()?;
+        info!("Recovered from device loss.");
 
-        info!("Creating command pool.");
-        let command_pool = create_command_pool(&queue_data, &logical_device)?;
+        Ok(())
+    }
 
-        info!("Creating command buffers.");
-        let command_buffers = create_command_buffers(&logical_device, &swapchain_data, &render_pass, &pipeline_data.pipeline, &framebuffers, &command_pool)?;
+    /// Destroys the logical device and everything made from it, then chooses a device again and
+    /// rebuilds the renderer on that. The recorder and any texture set are dropped, as they belong
+    /// to the old device, so the device reset hook should set them again. If the rebuild fails,
+    /// `device_lost` stays set and everything but rendering returns `Error::DeviceLost` until a
+    /// later frame rebuilds it.
+    ///
+    /// # Safety
+    /// Anything else the caller made from the old device must already have been dropped.
+    pub unsafe fn recreate_device(&mut self) -> error::Result<()> {
+        info!("Recreating device.");
+        self.device_lost = true;
+        self.recorder = None;
+
+        // lost or not, nothing may still be executing. a lost device returns straight away, and
+        // has no cache data to give back, otherwise the cache is saved for the new device to load
+        match self.logical_device.device_wait_idle() {
+            Ok(()) => if let Err(e) = self.pipeline_cache.save(&self.logical_device) {
+                warn!("Failed to save pipeline cache: {:?}", e);
+            },
+            Err(e) => warn!("Failed to wait for the device before recreating it: {:?}", e),
+        }
 
-        info!("Creating sync objects.");
-        let sync_objects = create_sync_objects(&logical_device, &swapchain_data)?;
+        // the surface only takes one swapchain at a time, so the old one and everything built on
+        // its images goes before the new one is made. the rest of the old objects are dropped as
+        // they are replaced
+        let extent = self.target.extent();
+        self.deletion_queue = DeletionQueue::new(self.frames.len());
+        self.framebuffers.clear();
+        self.target = RenderTarget::Offscreen(data::OffscreenData {
+            format: self.target.format(),
+            extent,
+            image_views: vec![],
+            images: vec![],
+            allocations: vec![],
+        });
 
-        let frame: usize = 0;
+        let objects = create_device_objects(&self.instance, self.window.as_ref(), self.surface_data.as_ref(), extent, &self.config, &self.scene_shaders)?;
+
+        // in declaration order, so the old device is destroyed after everything made from it
+        self.deletion_queue = objects.deletion_queue;
+        self.frames = objects.frames;
+        self.frame = 0;
+        self.images_in_flight = objects.images_in_flight;
+        self.descriptor_allocator = objects.descriptor_allocator;
+        self.texture = objects.texture;
+        self.mesh = objects.mesh;
+        self.command_pool = objects.command_pool;
+        self.framebuffers = objects.framebuffers;
+        self.pipeline_data = objects.pipeline_data;
+        self.pipeline_cache = objects.pipeline_cache;
+        self.scene_layout = objects.scene_layout;
+        self.render_pass = objects.render_pass;
+        self.msaa_color = objects.msaa_color;
+        self.msaa_samples = objects.msaa_samples;
+        self.depth = objects.depth;
+        self.target = objects.target;
+        self.allocator = objects.allocator;
+        self.queue_data = objects.queue_data;
+        self.logical_device = objects.logical_device;
+        self.physical_device_data = objects.physical_device_data;
+        self.resized = false;
+        self.device_lost = false;
+
+        // a hook that installs a new one replaces itself
+        if let Some(mut hook) = self.on_device_reset.take() {
+            let result = hook(self);
+            self.on_device_reset.get_or_insert(hook);
+            result?;
+        }
 
-        Ok(
-            Self {
-                entry,
-                instance,
-                debug_data,
-                window,
-                surface_data,
-                physical_device_data,
-                queue_data,
-                swapchain_data,
-                logical_device,
-                render_pass,
-                pipeline_data,
-                framebuffers,
-                command_pool,
-                command_buffers,
-                sync_objects,
-                frame,
-            }
-        )
+        Ok(())
     }
 
-    pub unsafe fn render_frame(
+    // returns the capture when one is requested and the frame actually got rendered
+    unsafe fn draw_frame(
         &mut self,
-    ) -> Result<()> {
-        let in_flight_fence = self.sync_objects.in_flight_fences[self.frame];
+        capture: bool,
+    ) -> error::Result<Option<FrameCapture>> {
+        // nothing to render to while minimized
+        if self.is_minimized() {
+            return Ok(None);
+        }
+
+        #[cfg(any(test, feature = "test-hooks"))]
+        if std::mem::take(&mut self.lose_device) {
+            return Err(vk::Result::ERROR_DEVICE_LOST.into());
+        }
+
+        let in_flight_fence = *self.frames[self.frame].in_flight;
         self.logical_device.wait_for_fences(&[in_flight_fence], true, u64::MAX)?;
+        self.deletion_queue.collect(|frame| Ok(self.logical_device.get_fence_status(*self.frames[frame].in_flight)?))?;
+
+        let image_index = match &self.target {
+            RenderTarget::Swapchain(swapchain_data) => {
+                let result = swapchain_data
+                    .loader
+                    .acquire_next_image(
+                        swapchain_data.swapchain,
+                        u64::MAX,
+                        *self.frames[self.frame].image_available,
+                        vk::Fence::null(),
+                    );
+
+                // suboptimal is still presentable, so only recreate straight away when out of date
+                match result {
+                    Ok((image_index, _)) => image_index as usize,
+                    Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => return self.recreate_swapchain().map(|_| None),
+                    Err(e) => return Err(anyhow::Error::from(e).context("Failed to acquire swapchain image.").into()),
+                }
+            },
+            // offscreen images are always available, just cycle through them
+            RenderTarget::Offscreen(_) => self.frame % self.target.image_count(),
+        };
 
-        let image_index = self.swapchain_data
-            .loader
-            .acquire_next_image(
-                self.swapchain_data.swapchain,
-                u64::MAX,
-                self.sync_objects.image_available_semaphores[self.frame],
-                vk::Fence::null(),
-            )?
-            .0 as usize;
-
-        let image_in_flight = self.sync_objects.images_in_flight[image_index];
+        let image_in_flight = self.images_in_flight[image_index];
         if !image_in_flight.is_null() {
             self.logical_device.wait_for_fences(&[image_in_flight], true, u64::MAX)?;
         }
 
-        self.sync_objects.images_in_flight[image_index] = in_flight_fence;
+        self.images_in_flight[image_index] = in_flight_fence;
 
-        let wait_semaphores = &[self.sync_objects.image_available_semaphores[self.frame]];
+        // this frame's uniform buffer, descriptor set and command pool are free again now its
+        // fence has signalled
+        let frame = &mut self.frames[self.frame];
+        frame.uniform_buffer.allocation.write(std::slice::from_ref(&self.uniforms))?;
+        if std::mem::take(&mut frame.stale_descriptor_set) {
+            write_texture_descriptors(&self.logical_device, frame.descriptor_set, &self.texture);
+        }
+        self.record_frame(image_index)?;
+
+        // copy out of the image in the same submission so it happens before presenting
+        let readback = match capture {
+            true => Some(capture::create_readback(
+                    &self.logical_device,
+                    &mut self.allocator,
+                    *self.command_pool,
+                    &self.target,
+                    self.target.image(image_index),
+            )?),
+            false => None,
+        };
+
+        let frame = &self.frames[self.frame];
+        let wait_semaphores = &[*frame.image_available];
         let wait_stages = &[vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT];
-        let command_buffers = &[self.command_buffers[image_index]];
-        let signal_semaphores = &[self.sync_objects.render_finished_semaphores[self.frame]];
-        let submit_info = vk::SubmitInfo::default()
-            .wait_semaphores(wait_semaphores)
-            .wait_dst_stage_mask(wait_stages)
-            .command_buffers(command_buffers)
-            .signal_semaphores(signal_semaphores);
+        let mut command_buffers = vec![frame.command_buffer];
+        if let Some(readback) = &readback {
+            command_buffers.push(readback.command_buffer);
+        }
+        let signal_semaphores = &[*frame.render_finished];
+        let mut submit_info = vk::SubmitInfo::default()
+            .command_buffers(&command_buffers);
+
+        // there is no acquire or present to synchronise with when offscreen
+        if let RenderTarget::Swapchain(_) = self.target {
+            submit_info = submit_info
+                .wait_semaphores(wait_semaphores)
+                .wait_dst_stage_mask(wait_stages)
+                .signal_semaphores(signal_semaphores);
+        }
 
         self.logical_device.reset_fences(&[in_flight_fence])?;
 
-        self.logical_device.queue_submit(self.queue_data.graphics, &[submit_info], self.sync_objects.in_flight_fences[self.frame])?;
+        self.logical_device.queue_submit(self.queue_data.graphics, &[submit_info], in_flight_fence)?;
+        self.deletion_queue.submitted(self.frame);
+
+        let result = match &self.target {
+            RenderTarget::Swapchain(swapchain_data) => {
+                let swapchains = &[swapchain_data.swapchain];
+                let image_indices = &[image_index as u32];
+                let present_info = vk::PresentInfoKHR::default()
+                    .wait_semaphores(signal_semaphores)
+                    .swapchains(swapchains)
+                    .image_indices(image_indices);
+
+                swapchain_data.loader.queue_present(self.queue_data.present, &present_info)
+            },
+            RenderTarget::Offscreen(_) => Ok(false),
+        };
+
+        let changed = matches!(result, Ok(true) | Err(vk::Result::ERROR_OUT_OF_DATE_KHR));
+
+        let frame_capture = match readback {
+            Some(readback) => {
+                self.logical_device.wait_for_fences(&[in_flight_fence], true, u64::MAX)?;
+
+                Some(capture::read_capture(&self.target, &readback)?)
+            },
+            None => None,
+        };
+
+        self.frame = (self.frame + 1) % self.frames.len();
+
+        // only out of date is fixed by recreating the swapchain, anything else e.g. a lost device
+        // is returned even when the window was resized
+        match result {
+            Err(e) if e != vk::Result::ERROR_OUT_OF_DATE_KHR => {
+                return Err(anyhow::Error::from(e).context("Failed to present swapchain image.").into());
+            },
+            _ => {},
+        }
+
+        if self.resized || changed {
+            self.resized = false;
+            self.recreate_swapchain()?;
+        }
+
+        Ok(frame_capture)
+    }
+
+    // rebuilds the scene pipeline if one of its shaders changed on disk,
+    // keeping the old one when the new shaders don't work
+    unsafe fn reload_changed_shaders(&mut self) -> error::Result<()> {
+        let Some(shader_watcher) = &mut self.shader_watcher else {
+            return Ok(());
+        };
 
-        let swapchains = &[self.swapchain_data.swapchain];
-        let image_indices = &[image_index as u32];
-        let present_info = vk::PresentInfoKHR::default()
-            .wait_semaphores(signal_semaphores)
-            .swapchains(swapchains)
-            .image_indices(image_indices);
+        let changed = shader_watcher.poll();
+        if !changed.iter().any(|c| shader::SCENE_SHADERS.iter().any(|s| s.name == *c)) {
+            return Ok(());
+        }
 
-        self.swapchain_data.loader.queue_present(self.queue_data.present, &present_info)?;
+        info!("Reloading shaders {}.", changed.join(", "));
 
-        self.frame = (self.frame + 1) & MAX_FRAMES_IN_FLIGHT;
+        // only the changed files are compiled, the rest stay as they last worked
+        let result = shader::SCENE_SHADERS
+            .iter()
+            .zip(&self.scene_shaders)
+            .map(|(s, (stage, bytecode))| match changed.contains(&s.name) {
+                true => Ok((s.stage, s.load()?)),
+                false => Ok((*stage, bytecode.clone())),
+            })
+            .collect::<Result<Vec<_>>>()
+            .map_err(Error::Shader)
+            .and_then(|shaders| {
+                let pipeline_data = create_pipeline(
+                    &self.logical_device,
+                    &self.pipeline_cache,
+                    &self.target,
+                    &self.render_pass,
+                    &Mesh::<TexturedVertex>::layout(),
+                    &self.scene_layout,
+                    &shaders,
+                    &self.config.depth,
+                    self.msaa_samples,
+                )?;
+
+                Ok((shaders, pipeline_data))
+            });
+
+        match result {
+            Ok((shaders, pipeline_data)) => {
+                self.scene_shaders = shaders;
+                self.deletion_queue.replace(&mut self.pipeline_data, pipeline_data);
+                info!("Rebuilt pipeline.");
+            },
+            Err(e) => error!("Failed to reload shaders, keeping the old pipeline: {:?}", e),
+        }
 
         Ok(())
     }
 
-    pub unsafe fn destroy(&mut self) {
-        self.logical_device.device_wait_idle().unwrap();
+    // replaces the default scene recording, called every frame with the render pass not yet begun
+    pub fn set_recorder<F: FnMut(&RecordContext) -> Result<()> + 'static>(&mut self, recorder: F) {
+        self.recorder = Some(Box::new(recorder));
+    }
 
-        self.sync_objects.in_flight_fences.iter().for_each(|f| self.logical_device.destroy_fence(*f, None));
-        self.sync_objects.render_finished_semaphores.iter().for_each(|s| self.logical_device.destroy_semaphore(*s, None));
-        self.sync_objects.image_available_semaphores.iter().for_each(|s| self.logical_device.destroy_semaphore(*s, None));
-        self.logical_device.destroy_command_pool(self.command_pool, None);
-        self.framebuffers.iter().for_each(|f| self.logical_device.destroy_framebuffer(*f, None));
-        self.logical_device.destroy_pipeline(self.pipeline_data.pipeline, None);
-        self.logical_device.destroy_pipeline_layout(self.pipeline_data.layout, None);
-        self.logical_device.destroy_render_pass(self.render_pass, None);
-        self.swapchain_data.image_views.iter().for_each(|v| self.logical_device.destroy_image_view(*v, None));
-        self.swapchain_data.loader.destroy_swapchain(self.swapchain_data.swapchain, None);
-        self.logical_device.destroy_device(None);
-        self.surface_data.loader.destroy_surface(self.surface_data.surface, None);
+    // makes the next frame fail as if the device was lost, as a real loss can't be caused on demand
+    #[cfg(any(test, feature = "test-hooks"))]
+    pub fn simulate_device_loss(&mut self) {
+        self.lose_device = true;
+    }
 
-        if VALIDATION_ENABLED {
-            self.debug_data
-                .as_ref()
-                .unwrap()
-                .utils_loader
-                .clone()
-                .destroy_debug_utils_messenger(self.debug_data.as_ref().unwrap().callback, None);
+    // called after recreate_device, e.g. to upload the scene's resources and set the recorder again
+    pub fn set_device_reset_hook<F: FnMut(&mut App) -> Result<()> + 'static>(&mut self, hook: F) {
+        self.on_device_reset = Some(Box::new(hook));
+    }
+
+    unsafe fn record_frame(&mut self, image_index: usize) -> error::Result<()> {
+        let frame = &self.frames[self.frame];
+        let command_buffer = frame.command_buffer;
+
+        self.logical_device.reset_command_pool(*frame.command_pool, vk::CommandPoolResetFlags::empty())?;
+
+        let begin_info = vk::CommandBufferBeginInfo::default()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+
+        self.logical_device.begin_command_buffer(command_buffer, &begin_info)?;
+
+        let context = RecordContext {
+            device: &self.logical_device,
+            command_buffer,
+            render_pass: *self.render_pass,
+            framebuffer: *self.framebuffers[image_index],
+            extent: self.target.extent(),
+            frame: self.frame,
+            image_index,
+            pipeline_data: &self.pipeline_data,
+            descriptor_set: frame.descriptor_set,
+            mesh: &self.mesh,
+        };
+
+        match &mut self.recorder {
+            Some(recorder) => recorder(&context)?,
+            None => record::record_default(&context)?,
+        }
+
+        self.logical_device.end_command_buffer(command_buffer)?;
+
+        Ok(())
+    }
+
+    /// Uploads vertices into device-local memory through a staging buffer.
+    ///
+    /// # Safety
+    /// The buffer must be dropped before the app, or handed to `retire` once frames stop drawing
+    /// it.
+    pub unsafe fn create_vertex_buffer<V: Vertex>(&mut self, vertices: &[V]) -> error::Result<VertexBuffer<V>> {
+        self.check_device()?;
+
+        Ok(VertexBuffer::create(&self.logical_device, &mut self.allocator, self.queue_data.graphics, *self.command_pool, vertices)?)
+    }
+
+    /// Uploads indices into device-local memory through a staging buffer.
+    ///
+    /// # Safety
+    /// The buffer must be dropped before the app, or handed to `retire` once frames stop drawing
+    /// it.
+    pub unsafe fn create_index_buffer<I: Index>(&mut self, indices: &[I]) -> error::Result<IndexBuffer<I>> {
+        self.check_device()?;
+
+        Ok(IndexBuffer::create(&self.logical_device, &mut self.allocator, self.queue_data.graphics, *self.command_pool, indices)?)
+    }
+
+    /// Decodes a png or jpeg into a sampled image.
+    ///
+    /// # Safety
+    /// The texture must be dropped before the app, unless it is handed to `set_texture`.
+    pub unsafe fn load_texture<P: AsRef<std::path::Path>>(&mut self, path: P) -> error::Result<Texture> {
+        self.check_device()?;
+
+        Ok(Texture::from_file(&self.logical_device, &mut self.allocator, self.queue_data.graphics, *self.command_pool, path)?)
+    }
+
+    /// Swaps the texture the scene is drawn with. Frames in flight keep using the previous one, so
+    /// their descriptor sets are only rewritten once each frame comes round again.
+    ///
+    /// # Safety
+    /// `texture` must have been made on the app's current device, e.g. with `load_texture`.
+    pub unsafe fn set_texture(&mut self, texture: Texture) -> error::Result<()> {
+        self.check_device()?;
+
+        self.deletion_queue.replace(&mut self.texture, texture);
+        self.frames.iter_mut().for_each(|f| f.stale_descriptor_set = true);
+
+        Ok(())
+    }
+
+    // keeps a resource the app no longer uses alive until the frames in flight are done with it
+    pub fn retire<T: 'static>(&mut self, resource: T) {
+        self.deletion_queue.retire(resource);
+    }
+
+    // a failed rebuild leaves no usable device or render target behind, so until rendering manages
+    // to rebuild them everything else reports the device as lost
+    fn check_device(&self) -> error::Result<()> {
+        match self.device_lost {
+            true => Err(Error::DeviceLost(vk::Result::ERROR_DEVICE_LOST)),
+            false => Ok(()),
+        }
+    }
+
+    pub fn is_minimized(&self) -> bool {
+        match &self.window {
+            Some(window) => {
+                let size = window.inner_size();
+                size.width == 0 || size.height == 0
+            },
+            None => false,
+        }
+    }
+
+    /// Rebuilds the swapchain and everything drawn into it to match the window, e.g. after a
+    /// resize.
+    ///
+    /// # Safety
+    /// The window the surface was made for must still be open.
+    pub unsafe fn recreate_swapchain(&mut self) -> error::Result<()> {
+        self.check_device()?;
+
+        // offscreen images never go out of date
+        let (Some(window), Some(surface_data), RenderTarget::Swapchain(swapchain_data)) = (&self.window, &self.surface_data, &self.target) else {
+            return Ok(());
+        };
+
+        // wait until the window is shown again, render_frame will retry
+        if self.is_minimized() {
+            self.resized = true;
+            return Ok(());
         }
 
-        self.instance.destroy_instance(None);
+        info!("Recreating swapchain.");
+
+        self.physical_device_data.swapchain_support = Some(data::SwapchainSupport::get(surface_data, self.physical_device_data.device)?);
+
+        let old_swapchain = swapchain_data.swapchain;
+        let new_swapchain_data = create_swapchain(
+            window,
+            &self.instance,
+            surface_data,
+            &self.physical_device_data,
+            &self.queue_data,
+            &self.logical_device,
+            old_swapchain,
+        )?;
+
+        // frames in flight still render to the old images, which are only retired once everything
+        // built on the new ones exists. on failure the old swapchain is kept, and as it is now out
+        // of date the next frame tries again
+        self.create_target_dependents(Some(RenderTarget::Swapchain(new_swapchain_data)))
+    }
+
+    /// Switches msaa on, off or to another sample count, rebuilding everything that depends on it.
+    /// Returns the sample count actually used, which is clamped to what the device supports.
+    ///
+    /// # Safety
+    /// Pipelines and framebuffers the caller built against the old render pass can't be used with
+    /// the new one.
+    pub unsafe fn set_msaa_samples(&mut self, samples: u32) -> error::Result<vk::SampleCountFlags> {
+        self.check_device()?;
+
+        let msaa_samples = choose_sample_count(&self.instance, &self.physical_device_data, samples);
+        self.config.msaa_samples = samples;
+
+        if msaa_samples == self.msaa_samples {
+            return Ok(msaa_samples);
+        }
+
+        info!("Switching msaa from {:?} to {:?}.", self.msaa_samples, msaa_samples);
+
+        let previous = std::mem::replace(&mut self.msaa_samples, msaa_samples);
+        if let Err(e) = self.create_target_dependents(None) {
+            self.msaa_samples = previous;
+            return Err(e);
+        }
+
+        Ok(msaa_samples)
+    }
+
+    // rebuilds everything that depends on the render target or sample count, for new_target if
+    // given. the old objects, and the old target, are only retired once all the new ones exist, so
+    // a failure leaves the app as it was
+    unsafe fn create_target_dependents(&mut self, new_target: Option<RenderTarget>) -> error::Result<()> {
+        let target = new_target.as_ref().unwrap_or(&self.target);
+
+        let depth = create_depth_image(
+            &self.instance,
+            &self.physical_device_data,
+            &self.logical_device,
+            &mut self.allocator,
+            target.extent(),
+            self.msaa_samples,
+        )?;
+        let msaa_color = match self.msaa_samples {
+            vk::SampleCountFlags::TYPE_1 => None,
+            _ => Some(create_msaa_color_image(&self.logical_device, &mut self.allocator, target, self.msaa_samples)?),
+        };
+        let render_pass = create_render_pass(&self.logical_device, target, depth.format, self.msaa_samples)?;
+        let pipeline_data = create_pipeline(
+            &self.logical_device,
+            &self.pipeline_cache,
+            target,
+            &render_pass,
+            &Mesh::<TexturedVertex>::layout(),
+            &self.scene_layout,
+            &self.scene_shaders,
+            &self.config.depth,
+            self.msaa_samples,
+        )?;
+        let framebuffers = create_framebuffers(&self.logical_device, target, &depth, msaa_color.as_ref(), &render_pass)?;
+
+        // users first, as the queue drops in the order things were retired
+        self.deletion_queue.replace(&mut self.framebuffers, framebuffers);
+        self.deletion_queue.replace(&mut self.pipeline_data, pipeline_data);
+        self.deletion_queue.replace(&mut self.render_pass, render_pass);
+        self.deletion_queue.replace(&mut self.msaa_color, msaa_color);
+        self.deletion_queue.replace(&mut self.depth, depth);
+
+        if let Some(target) = new_target {
+            self.deletion_queue.replace(&mut self.target, target);
+            self.images_in_flight = vec![vk::Fence::null(); self.target.image_count()];
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for App {
+    fn drop(&mut self) {
+        unsafe {
+            // nothing may be destroyed while the gpu could still be using it
+            if let Err(e) = self.logical_device.device_wait_idle() {
+                warn!("Failed to wait for the device before destroying it: {:?}", e);
+            }
+
+            if let Err(e) = self.pipeline_cache.save(&self.logical_device) {
+                warn!("Failed to save pipeline cache: {:?}", e);
+            }
+        }
     }
 }
 
@@ -217,23 +831,38 @@ impl App {
  * Functions
  */
 
-fn create_instance(
-        window: &winit::window::Window,
+pub fn create_instance(
+        window: Option<&winit::window::Window>,
         entry: &Entry,
-    ) -> Result<Instance> {
+    ) -> error::Result<Instance> {
     // validation layer
-    let layer_names = [ unsafe { CStr::from_bytes_with_nul_unchecked(
-        b"VK_LAYER_KHRONOS_validation\0",
-    ) }];
+    let layer_names = [c"VK_LAYER_KHRONOS_validation"];
 
+    // only request the validation layer when it is actually installed, machines without
+    // the SDK (CI, servers) should still be able to create an instance
+    let available_layers = unsafe { entry.enumerate_instance_layer_properties()? };
     let layer_names_raw: Vec<*const c_char> = layer_names
         .iter()
+        .filter(|&&name| {
+            let available = available_layers
+                .iter()
+                .any(|l| unsafe { CStr::from_ptr(l.layer_name.as_ptr()) } == name);
+
+            if VALIDATION_ENABLED && !available {
+                warn!("Validation layer {:?} is not available.", name);
+            }
+
+            VALIDATION_ENABLED && available
+        })
         .map(|raw_name| raw_name.as_ptr())
         .collect();
 
-    let mut extension_names = ash_window::enumerate_required_extensions(window.display_handle()?.as_raw())
-            .unwrap()
-            .to_vec();
+    // surface extensions are only needed when presenting to a window
+    let mut extension_names = match window {
+        Some(window) => ash_window::enumerate_required_extensions(window.display_handle().map_err(anyhow::Error::from)?.as_raw())?
+            .to_vec(),
+        None => vec![],
+    };
 
     // add debug utils extension if needed
     if VALIDATION_ENABLED {
@@ -253,16 +882,28 @@ fn create_instance(
         vk::InstanceCreateFlags::default()
     };
 
-    let app_name = CString::new(WINDOW_TITLE).unwrap();
-    let engine_name = CString::new("Vulkan Engine").unwrap();
+    // named up front, as the driver only reports that one of them is missing
+    let available_extensions = unsafe { entry.enumerate_instance_extension_properties(None)? };
+    for &name in &extension_names {
+        let name = unsafe { CStr::from_ptr(name) };
+        if !available_extensions.iter().any(|e| unsafe { CStr::from_ptr(e.extension_name.as_ptr()) } == name) {
+            return Err(Error::MissingExtension {
+                name: Some(name.to_string_lossy().into_owned()),
+                result: vk::Result::ERROR_EXTENSION_NOT_PRESENT,
+            });
+        }
+    }
+
+    let app_name = CString::new(WINDOW_TITLE).map_err(anyhow::Error::from)?;
+    let engine_name = c"Vulkan Engine";
 
     // create struct that holds the applications info
     let app_info = vk::ApplicationInfo::default()
         .application_name(&app_name)
         .application_version(0)
-        .engine_name(&engine_name)
+        .engine_name(engine_name)
         .engine_version(0)
-        .api_version(vk::make_api_version(0, 1, 0, 0));
+        .api_version(vk::API_VERSION_1_1);
 
     // create the struct that holds instance creation info
     let mut create_info = vk::InstanceCreateInfo::default()
@@ -288,18 +929,157 @@ fn create_instance(
     }
 
     // actually create the instance
-    let instance: Instance = unsafe { entry
-        .create_instance(&create_info, None)
-        .expect("Instance creation failed.")
-    };
+    let instance: Instance = unsafe { entry.create_instance(&create_info, None)? };
 
     Ok(instance)
 }
 
+// builds the logical device and everything made from it, at startup and again after the device is lost
+fn create_device_objects(
+    instance: &Instance,
+    window: Option<&winit::window::Window>,
+    surface_data: Option<&data::SurfaceData>,
+    headless_extent: vk::Extent2D,
+    config: &Config,
+    scene_shaders: &[(vk::ShaderStageFlags, Bytecode)],
+) -> error::Result<DeviceObjects> {
+    /* physical device */
+    info!("Choosing device.");
+    // get required device extension names
+    let mut device_extension_names = vec![
+        #[cfg(any(target_os = "macos", target_os = "ios"))]
+        ash::khr::portability_subset::NAME,
+    ];
+
+    if surface_data.is_some() {
+        device_extension_names.push(swapchain::NAME);
+    }
+
+    // get required device extension names as pointers
+    let device_extension_names_raw = device_extension_names.iter().map(|e| e.as_ptr()).collect::<Vec<_>>();
+
+    let physical_device_data = choose_device(instance, surface_data, &device_extension_names, config.device.as_ref())?;
+
+    let queue_family_indices = unsafe { data::QueueFamilyIndices::get(instance, surface_data, physical_device_data.device)? };
+    
+    info!("Creating logical device.");
+    let logical_device = OwnedDevice::new(create_logical_device(instance, &physical_device_data, &queue_family_indices, &device_extension_names_raw)?);
+
+    let queue_data = unsafe { data::QueueData::get(queue_family_indices, &logical_device) };
+
+    let mut allocator = Allocator::new(instance, physical_device_data.device, &logical_device);
+
+    let target = match (window, surface_data) {
+        (Some(window), Some(surface_data)) => {
+            info!("Creating swapchain.");
+            RenderTarget::Swapchain(create_swapchain(window, instance, surface_data, &physical_device_data, &queue_data, &logical_device, vk::SwapchainKHR::null())?)
+        },
+        _ => {
+            info!("Creating offscreen images.");
+            RenderTarget::Offscreen(create_offscreen_images(instance, &physical_device_data, &logical_device, &mut allocator, headless_extent, config.frames_in_flight)?)
+        },
+    };
+
+    let msaa_samples = choose_sample_count(instance, &physical_device_data, config.msaa_samples);
+
+    info!("Creating depth image.");
+    let depth = create_depth_image(instance, &physical_device_data, &logical_device, &mut allocator, target.extent(), msaa_samples)?;
+
+    let msaa_color = match msaa_samples {
+        vk::SampleCountFlags::TYPE_1 => None,
+        _ => {
+            info!("Creating {:?} msaa color image.", msaa_samples);
+            Some(create_msaa_color_image(&logical_device, &mut allocator, &target, msaa_samples)?)
+        },
+    };
+
+    info!("Creating render pass.");
+    let render_pass = create_render_pass(&logical_device, &target, depth.format, msaa_samples)?;
+
+    info!("Creating descriptor set layouts from the scene shaders.");
+    let scene_layout = create_scene_layout(&logical_device, scene_shaders)?;
+
+    info!("Loading pipeline cache.");
+    let pipeline_cache = unsafe { PipelineCache::load(instance, physical_device_data.device, &logical_device, config.pipeline_cache.as_deref())? };
+
+    info!("Creating pipeline.");
+    let pipeline_start = std::time::Instant::now();
+    let pipeline_data = create_pipeline(
+        &logical_device,
+        &pipeline_cache,
+        &target,
+        &render_pass,
+        &Mesh::<TexturedVertex>::layout(),
+        &scene_layout,
+        scene_shaders,
+        &config.depth,
+        msaa_samples,
+    )?;
+    info!(
+        "Created pipeline in {:.2?} from a {} pipeline cache.",
+        pipeline_start.elapsed(),
+        match pipeline_cache.loaded_size {
+            0 => "cold".to_string(),
+            n => format!("warm {} byte", n),
+        },
+    );
+
+    info!("Creating framebuffers.");
+    let framebuffers = create_framebuffers(&logical_device, &target, &depth, msaa_color.as_ref(), &render_pass)?;
+
+    info!("Creating command pool.");
+    let command_pool = create_command_pool(&queue_data, &logical_device)?;
+
+    info!("Creating mesh.");
+    let mesh = unsafe { Mesh::create(&logical_device, &mut allocator, queue_data.graphics, *command_pool, &TRIANGLE_VERTICES, &TRIANGLE_INDICES)? };
+
+    info!("Creating default texture.");
+    let texture = unsafe { Texture::solid(&logical_device, &mut allocator, queue_data.graphics, *command_pool, [255; 4])? };
+
+    info!("Creating {} frames in flight.", config.frames_in_flight);
+    let mut descriptor_allocator = DescriptorAllocator::new(descriptor::DEFAULT_POOL_RATIOS);
+    let frames = create_frames(
+        &logical_device,
+        &mut allocator,
+        &mut descriptor_allocator,
+        &queue_data,
+        *scene_layout.set_layouts[0],
+        &texture,
+        config.frames_in_flight,
+    )?;
+
+    let images_in_flight = vec![vk::Fence::null(); target.image_count()];
+
+    Ok(
+        DeviceObjects {
+            deletion_queue: DeletionQueue::new(frames.len()),
+            frames,
+            images_in_flight,
+            descriptor_allocator,
+            texture,
+            mesh,
+            command_pool,
+            framebuffers,
+            pipeline_data,
+            pipeline_cache,
+            scene_layout,
+            render_pass,
+            msaa_color,
+            msaa_samples,
+            depth,
+            target,
+            allocator,
+            queue_data,
+            logical_device,
+            physical_device_data,
+        }
+    )
+}
+
 fn create_debug_data (
     instance: &Instance,
     entry: &Entry,
-    ) -> Option<data::DebugData> {
+    ) -> Result<Option<data::DebugData>> {
     // setup debug create info
     let debug_info = vk::DebugUtilsMessengerCreateInfoEXT::default()
         .message_severity(vk::DebugUtilsMessageSeverityFlagsEXT::ERROR
@@ -314,8 +1094,8 @@ fn create_debug_data (
     let mut debug_data: Option<data::DebugData> = None;
 
     if VALIDATION_ENABLED {
-        let utils_loader = debug_utils::Instance::new(entry, &instance);
-        let callback = unsafe { utils_loader.create_debug_utils_messenger(&debug_info, None).expect("Failed to create debug callback.") };
+        let utils_loader = debug_utils::Instance::new(entry, instance);
+        let callback = unsafe { utils_loader.create_debug_utils_messenger(&debug_info, None).context("Failed to create debug callback.")? };
 
         debug_data = Some( data::DebugData {
             utils_loader,
@@ -323,10 +1103,10 @@ fn create_debug_data (
         });
     }
 
-    debug_data
+    Ok(debug_data)
 }
 
-fn create_surface(
+pub fn create_surface(
         entry: &Entry,
         instance: &Instance,
         window: &winit::window::Window,
@@ -343,7 +1123,7 @@ fn create_surface(
     };
 
 
-    let loader = surface::Instance::new(&entry, &instance);
+    let loader = surface::Instance::new(entry, instance);
 
     Ok(
         data::SurfaceData {
@@ -355,70 +1135,78 @@ fn create_surface(
 
 fn choose_device(
         instance: &Instance,
-        surface_data: &data::SurfaceData,
-        device_extension_names: &Vec<&CStr>,
+        surface_data: Option<&data::SurfaceData>,
+        device_extension_names: &[&CStr],
+        selector: Option<&DeviceSelector>,
     ) -> Result<data::PhysicalDeviceData> {
     // check if any vulkan supported GPUs exist
     info!("Enumerating physical devices.");
-    let phys_devices = unsafe { match instance.enumerate_physical_devices() {
-        Ok(pdevices) => pdevices,
-        Err(e) => return Err(anyhow!("Failed to find GPUs with Vulkan support: {:?}", e))
-    } };
+    let phys_devices = unsafe { instance.enumerate_physical_devices().context("Failed to find GPUs with Vulkan support.")? };
 
-    let mut phys_device = Err(());
-    let mut swapchain_support = Err(());
-    
-    // iterate through devices
-    for pdevice in phys_devices {
-        // check for required queue families
-        if let Ok(_) = unsafe { data::QueueFamilyIndices::get(instance, surface_data, pdevice) } {
-            // get the devices extension properties
-            let extensions = unsafe {
-                instance.enumerate_device_extension_properties(pdevice)?
-                    .iter()
-                    .map(|e| CStr::from_ptr(e.extension_name.as_ptr()))
-                    .collect::<Vec<_>>()
-            };
+    let candidates = phys_devices
+        .into_iter()
+        .enumerate()
+        .map(|(i, pdevice)| unsafe { DeviceCandidate::get(instance, surface_data, device_extension_names, i, pdevice) })
+        .collect::<Vec<_>>();
 
-            // check for needed extensions
-            if !device_extension_names
-                    .iter()
-                    .all(|e| extensions.contains(e)) {
-                break;
-            }
+    for candidate in &candidates {
+        if candidate.is_suitable() {
+            info!("Candidate {}: score {}.", candidate, candidate.score());
+        } else {
+            info!("Candidate {}: rejected. {}", candidate, candidate.rejections.join(" "));
+        }
+    }
 
-            swapchain_support = Ok(
-                unsafe {
-                    data::SwapchainSupport::get(surface_data, pdevice)?
-                }
-            );
+    // an explicit selection wins over scoring, but still has to be usable
+    let selector = match selector {
+        Some(selector) => Some(selector.clone()),
+        None => DeviceSelector::from_env()?,
+    };
+
+    // every candidate's rejections, for when none of them are suitable
+    let rejections = candidates
+        .iter()
+        .map(|c| format!("{}: {}", c, c.rejections.join(" ")))
+        .collect::<Vec<_>>();
+
+    let chosen = match &selector {
+        Some(selector) => {
+            let candidate = candidates
+                .into_iter()
+                .find(|c| selector.matches(c))
+                .ok_or_else(|| Error::NoSuitableDevice(format!("No device matches {}.", selector)))?;
 
-            if swapchain_support.as_ref().unwrap().formats.is_empty() ||
-               swapchain_support.as_ref().unwrap().present_modes.is_empty() {
-                break;
+            if !candidate.is_suitable() {
+                return Err(Error::NoSuitableDevice(format!("Selected device {} is not suitable. {}", candidate, candidate.rejections.join(" "))).into());
             }
 
-            phys_device = Ok(pdevice);
+            candidate
+        },
+        None => candidates
+            .into_iter()
+            .filter(|c| c.is_suitable())
+            .max_by_key(|c| c.score())
+            .ok_or_else(|| Error::NoSuitableDevice(match rejections.is_empty() {
+                true => "No devices with Vulkan support.".to_string(),
+                false => rejections.join(" "),
+            }))?,
+    };
 
-        }
-    }
+    info!("Using device {}.", chosen);
 
-    match (phys_device, swapchain_support) {
-        (Ok(device), Ok(swapchain_support)) => Ok(
-            data::PhysicalDeviceData {
-                device,
-                swapchain_support,
-            }
-        ),
-        _ => Err(anyhow!("Failed to find suitable device.")),
-    }
+    Ok(
+        data::PhysicalDeviceData {
+            device: chosen.device,
+            swapchain_support: chosen.swapchain_support,
+        }
+    )
 }
 
 fn create_logical_device(
         instance: &Instance,
         physical_device_data: &data::PhysicalDeviceData,
         queue_family_indices: &data::QueueFamilyIndices,
-        device_extension_names_raw: &Vec<*const i8>,
+        device_extension_names_raw: &[*const c_char],
     ) -> Result<Device> {
     let mut unique_indices = HashSet::new();
     unique_indices.insert(queue_family_indices.graphics);
@@ -439,15 +1227,14 @@ fn create_logical_device(
 
     let device_create_info = vk::DeviceCreateInfo::default()
         .queue_create_infos(&queue_infos)
-        .enabled_extension_names(&device_extension_names_raw)
+        .enabled_extension_names(device_extension_names_raw)
         .enabled_features(&features);
 
     // create logical device
     let device: Device = unsafe {
-        match instance.create_device(physical_device_data.device, &device_create_info, None) {
-            Ok(d) => d,
-            Err(e) => return Err(anyhow!("Failed to create logical device: {:?}", e))
-        }
+        instance
+            .create_device(physical_device_data.device, &device_create_info, None)
+            .context("Failed to create logical device.")?
     };
 
     Ok(device)
@@ -460,19 +1247,30 @@ fn create_swapchain(
         physical_device_data: &data::PhysicalDeviceData,
         queue_data: &data::QueueData,
         device: &Device,
+        old_swapchain: vk::SwapchainKHR,
     ) -> Result<data::SwapchainData> {
-    let swapchain_surface_format = physical_device_data.swapchain_support.get_surface_format();
+    let swapchain_support = physical_device_data.swapchain_support
+        .as_ref()
+        .ok_or_else(|| anyhow!("Missing swapchain support details."))?;
+
+    let swapchain_surface_format = swapchain_support
+        .get_surface_format()
+        .ok_or_else(|| anyhow!("Surface has no formats."))?;
     let format = swapchain_surface_format.format;
-    let swapchain_present_mode = physical_device_data.swapchain_support.get_present_mode();
-    let extent = physical_device_data.swapchain_support.get_extent(window);
+    let swapchain_present_mode = swapchain_support.get_present_mode();
+    let extent = swapchain_support.get_extent(window);
 
-    let mut swapchain_image_count = physical_device_data.swapchain_support.capabilities.min_image_count + 1;
-    if physical_device_data.swapchain_support.capabilities.max_image_count != 0
-        && swapchain_image_count > physical_device_data.swapchain_support.capabilities.max_image_count 
+    let mut swapchain_image_count = swapchain_support.capabilities.min_image_count + 1;
+    if swapchain_support.capabilities.max_image_count != 0
+        && swapchain_image_count > swapchain_support.capabilities.max_image_count 
     {
-            swapchain_image_count = physical_device_data.swapchain_support.capabilities.max_image_count;
+            swapchain_image_count = swapchain_support.capabilities.max_image_count;
     }
 
+    // allow copying out of the swapchain images for captures where supported
+    let image_usage = vk::ImageUsageFlags::COLOR_ATTACHMENT
+        | (swapchain_support.capabilities.supported_usage_flags & vk::ImageUsageFlags::TRANSFER_SRC);
+
     let mut swapchain_qf_indices = vec![];
     let image_sharing_mode = if queue_data.family_indices.graphics != queue_data.family_indices.present {
         swapchain_qf_indices.push(queue_data.family_indices.graphics);
@@ -489,42 +1287,53 @@ fn create_swapchain(
         .image_color_space(swapchain_surface_format.color_space)
         .image_extent(extent)
         .image_array_layers(1)
-        .image_usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
+        .image_usage(image_usage)
         .image_sharing_mode(image_sharing_mode)
         .queue_family_indices(&swapchain_qf_indices)
-        .pre_transform(physical_device_data.swapchain_support.capabilities.current_transform)
+        .pre_transform(swapchain_support.capabilities.current_transform)
         .composite_alpha(vk::CompositeAlphaFlagsKHR::OPAQUE)
         .present_mode(swapchain_present_mode)
         .clipped(true)
-        .old_swapchain(vk::SwapchainKHR::null());
+        .old_swapchain(old_swapchain);
 
     let loader = swapchain::Device::new(instance, device);
 
-    let swapchain = unsafe { loader
-        .create_swapchain(&swapchain_create_info, None)
-        .unwrap() };
+    let swapchain = unsafe { loader.create_swapchain(&swapchain_create_info, None).context("Failed to create swapchain.")? };
 
-    let images = unsafe { loader.get_swapchain_images(swapchain)? };
+    // owned straight away, so it is destroyed if getting its images fails
+    let mut swapchain_data = data::SwapchainData {
+        swapchain,
+        loader,
+        format,
+        extent,
+        images: vec![],
+        image_views: vec![],
+    };
 
-    let image_views = create_swapchain_image_views(&images, &format, device)?;
+    swapchain_data.images = unsafe { swapchain_data.loader.get_swapchain_images(swapchain)? };
+    swapchain_data.image_views = create_image_views(&swapchain_data.images, &format, device)?;
 
-    Ok(
-        data::SwapchainData {
-            swapchain,
-            loader,
-            format,
-            extent,
-            images,
-            image_views,
-        }
-    )
+    Ok(swapchain_data)
 }
 
-fn create_swapchain_image_views(
-        swapchain_images: &Vec<vk::Image>,
-        swapchain_format: &vk::Format,
+fn create_image_views(
+        images: &[vk::Image],
+        format: &vk::Format,
         device: &Device,
-    ) -> Result<Vec<vk::ImageView>> {
+    ) -> Result<Vec<Owned<vk::ImageView>>> {
+    images
+        .iter()
+        .map(|i| create_image_view(device, *i, *format, vk::ImageAspectFlags::COLOR))
+        .collect()
+}
+
+// a 2d view over the first mip level and array layer of an image
+fn create_image_view(
+    device: &Device,
+    image: vk::Image,
+    format: vk::Format,
+    aspect_mask: vk::ImageAspectFlags,
+) -> Result<Owned<vk::ImageView>> {
     let components = vk::ComponentMapping::default()
         .r(vk::ComponentSwizzle::IDENTITY)
         .g(vk::ComponentSwizzle::IDENTITY)
@@ -532,323 +1341,518 @@ fn create_swapchain_image_views(
         .a(vk::ComponentSwizzle::IDENTITY);
 
     let subresource_range = vk::ImageSubresourceRange::default()
-        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .aspect_mask(aspect_mask)
         .base_mip_level(0)
         .level_count(1)
         .base_array_layer(0)
         .layer_count(1);
 
+    let info = vk::ImageViewCreateInfo::default()
+        .image(image)
+        .view_type(vk::ImageViewType::TYPE_2D)
+        .format(format)
+        .components(components)
+        .subresource_range(subresource_range);
+
+    unsafe { Ok(Owned::new(device, device.create_image_view(&info, None)?)) }
+}
+
+fn create_offscreen_images(
+    instance: &Instance,
+    physical_device_data: &data::PhysicalDeviceData,
+    device: &Device,
+    allocator: &mut Allocator,
+    extent: vk::Extent2D,
+    image_count: usize,
+) -> Result<data::OffscreenData> {
+    // match the format the swapchain would normally pick so output is comparable
+    let format = [vk::Format::B8G8R8A8_SRGB, vk::Format::R8G8B8A8_SRGB]
+        .into_iter()
+        .find(|f| {
+            let properties = unsafe { instance.get_physical_device_format_properties(physical_device_data.device, *f) };
+            properties.optimal_tiling_features.contains(vk::FormatFeatureFlags::COLOR_ATTACHMENT | vk::FormatFeatureFlags::TRANSFER_SRC)
+        })
+        .ok_or_else(|| anyhow!("No supported offscreen color format."))?;
+
+    let mut images = vec![];
+    let mut allocations = vec![];
+
+    // one image per frame in flight, standing in for the swapchain images
+    for _ in 0..image_count {
+        let image_info = vk::ImageCreateInfo::default()
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(format)
+            .extent(vk::Extent3D { width: extent.width, height: extent.height, depth: 1 })
+            .mip_levels(1)
+            .array_layers(1)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSFER_SRC)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .initial_layout(vk::ImageLayout::UNDEFINED);
+
+        let (image, allocation) = unsafe { allocator.create_image(&image_info, MemoryUsage::GPU_ONLY)? };
+
+        images.push(image);
+        allocations.push(allocation);
+    }
+
+    let image_views = images
+        .iter()
+        .map(|i| create_image_view(device, **i, format, vk::ImageAspectFlags::COLOR))
+        .collect::<Result<Vec<_>>>()?;
+
     Ok(
-        swapchain_images
-            .iter()
-            .map(|i| {
-                let info = vk::ImageViewCreateInfo::default()
-                    .image(*i)
-                    .view_type(vk::ImageViewType::TYPE_2D)
-                    .format(*swapchain_format)
-                    .components(components)
-                    .subresource_range(subresource_range);
-
-                unsafe { device.create_image_view(&info, None) }
-            })
-            .collect::<Result<Vec<_>, _>>()?
+        data::OffscreenData {
+            format,
+            extent,
+            image_views,
+            images,
+            allocations,
+        }
+    )
+}
+
+// first of the candidates usable as an optimal tiling depth attachment
+fn get_depth_format(
+    instance: &Instance,
+    physical_device: vk::PhysicalDevice,
+) -> Result<vk::Format> {
+    [vk::Format::D32_SFLOAT, vk::Format::D32_SFLOAT_S8_UINT, vk::Format::D24_UNORM_S8_UINT]
+        .into_iter()
+        .find(|f| {
+            let properties = unsafe { instance.get_physical_device_format_properties(physical_device, *f) };
+            properties.optimal_tiling_features.contains(vk::FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT)
+        })
+        .ok_or_else(|| anyhow!("No supported depth format."))
+}
+
+fn create_depth_image(
+    instance: &Instance,
+    physical_device_data: &data::PhysicalDeviceData,
+    device: &Device,
+    allocator: &mut Allocator,
+    extent: vk::Extent2D,
+    samples: vk::SampleCountFlags,
+) -> Result<AttachmentImage> {
+    let format = get_depth_format(instance, physical_device_data.device)?;
+
+    // attachment views of combined formats need both aspects
+    let aspect_mask = match format {
+        vk::Format::D32_SFLOAT => vk::ImageAspectFlags::DEPTH,
+        _ => vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL,
+    };
+
+    create_attachment_image(device, allocator, extent, format, samples, vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT, aspect_mask)
+}
+
+// only ever rendered to and resolved, so its contents never need to leave the tile memory
+fn create_msaa_color_image(
+    device: &Device,
+    allocator: &mut Allocator,
+    target: &data::RenderTarget,
+    samples: vk::SampleCountFlags,
+) -> Result<AttachmentImage> {
+    create_attachment_image(
+        device,
+        allocator,
+        target.extent(),
+        target.format(),
+        samples,
+        vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::TRANSIENT_ATTACHMENT,
+        vk::ImageAspectFlags::COLOR,
     )
 }
 
+fn create_attachment_image(
+    device: &Device,
+    allocator: &mut Allocator,
+    extent: vk::Extent2D,
+    format: vk::Format,
+    samples: vk::SampleCountFlags,
+    usage: vk::ImageUsageFlags,
+    aspect_mask: vk::ImageAspectFlags,
+) -> Result<AttachmentImage> {
+    let image_info = vk::ImageCreateInfo::default()
+        .image_type(vk::ImageType::TYPE_2D)
+        .format(format)
+        .extent(vk::Extent3D { width: extent.width, height: extent.height, depth: 1 })
+        .mip_levels(1)
+        .array_layers(1)
+        .samples(samples)
+        .tiling(vk::ImageTiling::OPTIMAL)
+        .usage(usage)
+        .sharing_mode(vk::SharingMode::EXCLUSIVE)
+        .initial_layout(vk::ImageLayout::UNDEFINED);
+
+    let (image, allocation) = unsafe { allocator.create_image(&image_info, MemoryUsage::GPU_ONLY)? };
+    let view = create_image_view(device, *image, format, aspect_mask)?;
+
+    Ok(
+        AttachmentImage {
+            format,
+            samples,
+            view,
+            image,
+            allocation,
+        }
+    )
+}
+
+// highest sample count supported for both colour and depth attachments
+fn get_max_usable_sample_count(
+    instance: &Instance,
+    physical_device: vk::PhysicalDevice,
+) -> vk::SampleCountFlags {
+    let limits = unsafe { instance.get_physical_device_properties(physical_device).limits };
+    let counts = limits.framebuffer_color_sample_counts & limits.framebuffer_depth_sample_counts;
+
+    [
+        vk::SampleCountFlags::TYPE_64,
+        vk::SampleCountFlags::TYPE_32,
+        vk::SampleCountFlags::TYPE_16,
+        vk::SampleCountFlags::TYPE_8,
+        vk::SampleCountFlags::TYPE_4,
+        vk::SampleCountFlags::TYPE_2,
+    ]
+        .into_iter()
+        .find(|c| counts.contains(*c))
+        .unwrap_or(vk::SampleCountFlags::TYPE_1)
+}
+
+// sample counts are single bits, so a request is rounded down to a power of two and
+// clamped to the device maximum
+fn choose_sample_count(
+    instance: &Instance,
+    physical_device_data: &data::PhysicalDeviceData,
+    requested: u32,
+) -> vk::SampleCountFlags {
+    let max = get_max_usable_sample_count(instance, physical_device_data.device);
+
+    let requested = match requested {
+        0 | 1 => 1,
+        n => 1 << (31 - n.leading_zeros()),
+    };
+
+    if requested > max.as_raw() {
+        warn!("{}x msaa is not supported, using {:?}.", requested, max);
+        return max;
+    }
+
+    vk::SampleCountFlags::from_raw(requested)
+}
+
 fn create_render_pass(
     device: &Device,
-    swapchain_data: &data::SwapchainData,
-) -> Result<vk::RenderPass> {
+    target: &data::RenderTarget,
+    depth_format: vk::Format,
+    samples: vk::SampleCountFlags,
+) -> Result<Owned<vk::RenderPass>> {
+    let msaa = samples != vk::SampleCountFlags::TYPE_1;
+
+    // with msaa this is the multisampled image, which is resolved into the target at the end
     let color_attachment = vk::AttachmentDescription::default()
-        .format(swapchain_data.format)
-        .samples(vk::SampleCountFlags::TYPE_1)
+        .format(target.format())
+        .samples(samples)
         .load_op(vk::AttachmentLoadOp::CLEAR)
-        .store_op(vk::AttachmentStoreOp::STORE)
+        .store_op(if msaa { vk::AttachmentStoreOp::DONT_CARE } else { vk::AttachmentStoreOp::STORE })
         .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
         .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
         .initial_layout(vk::ImageLayout::UNDEFINED)
-        .final_layout(vk::ImageLayout::PRESENT_SRC_KHR);
+        .final_layout(if msaa { vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL } else { target.final_layout() });
 
     let color_attachment_ref = vk::AttachmentReference::default()
         .attachment(0)
         .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
 
+    // the contents are never needed after the pass
+    let depth_attachment = vk::AttachmentDescription::default()
+        .format(depth_format)
+        .samples(samples)
+        .load_op(vk::AttachmentLoadOp::CLEAR)
+        .store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+        .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .initial_layout(vk::ImageLayout::UNDEFINED)
+        .final_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
+
+    let depth_attachment_ref = vk::AttachmentReference::default()
+        .attachment(1)
+        .layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL);
+
+    let resolve_attachment = vk::AttachmentDescription::default()
+        .format(target.format())
+        .samples(vk::SampleCountFlags::TYPE_1)
+        .load_op(vk::AttachmentLoadOp::DONT_CARE)
+        .store_op(vk::AttachmentStoreOp::STORE)
+        .stencil_load_op(vk::AttachmentLoadOp::DONT_CARE)
+        .stencil_store_op(vk::AttachmentStoreOp::DONT_CARE)
+        .initial_layout(vk::ImageLayout::UNDEFINED)
+        .final_layout(target.final_layout());
+
+    let resolve_attachment_ref = vk::AttachmentReference::default()
+        .attachment(2)
+        .layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL);
+
     let color_attachments = &[color_attachment_ref];
-    let subpass = vk::SubpassDescription::default()
+    let resolve_attachments = &[resolve_attachment_ref];
+    let mut subpass = vk::SubpassDescription::default()
         .pipeline_bind_point(vk::PipelineBindPoint::GRAPHICS)
-        .color_attachments(color_attachments);
+        .color_attachments(color_attachments)
+        .depth_stencil_attachment(&depth_attachment_ref);
 
+    if msaa {
+        subpass = subpass.resolve_attachments(resolve_attachments);
+    }
+
+    // the depth image is shared between frames, so also wait for the previous frame's depth writes
     let dependency = vk::SubpassDependency::default()
         .src_subpass(vk::SUBPASS_EXTERNAL)
         .dst_subpass(0)
+        .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::LATE_FRAGMENT_TESTS)
+        .src_access_mask(vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE)
+        .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | vk::PipelineStageFlags::EARLY_FRAGMENT_TESTS)
+        .dst_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE | vk::AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE);
+
+    // makes the final layout transition visible to captures copying out of the image
+    let readback_dependency = vk::SubpassDependency::default()
+        .src_subpass(0)
+        .dst_subpass(vk::SUBPASS_EXTERNAL)
         .src_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
-        .src_access_mask(vk::AccessFlags::empty())
-        .dst_stage_mask(vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT)
-        .dst_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE);
+        .src_access_mask(vk::AccessFlags::COLOR_ATTACHMENT_WRITE)
+        .dst_stage_mask(vk::PipelineStageFlags::TRANSFER)
+        .dst_access_mask(vk::AccessFlags::TRANSFER_READ);
 
-    let attachments = &[color_attachment];
+    let attachments = match msaa {
+        true => vec![color_attachment, depth_attachment, resolve_attachment],
+        false => vec![color_attachment, depth_attachment],
+    };
     let subpasses = &[subpass];
-    let dependencies = &[dependency];
+    let dependencies = &[dependency, readback_dependency];
     let info = vk::RenderPassCreateInfo::default()
-        .attachments(attachments)
+        .attachments(&attachments)
         .subpasses(subpasses)
         .dependencies(dependencies);
 
-    unsafe { Ok(device.create_render_pass(&info, None)?) }
+    unsafe { Ok(Owned::new(device, device.create_render_pass(&info, None)?)) }
 }
 
+#[allow(clippy::too_many_arguments)]
 fn create_pipeline(
     device: &Device,
-    swapchain_data: &data::SwapchainData,
+    pipeline_cache: &PipelineCache,
+    target: &data::RenderTarget,
     render_pass: &vk::RenderPass,
+    vertex_layout: &VertexLayout,
+    layout: &ReflectedLayout,
+    shaders: &[(vk::ShaderStageFlags, Bytecode)],
+    depth_config: &DepthConfig,
+    samples: vk::SampleCountFlags,
 ) -> Result<PipelineData> {
-    let vert = include_bytes!("../shaders/vert.spv");
-    let frag = include_bytes!("../shaders/frag.spv");
-
-    let vert_shader_module = create_shader_module(&device, vert)?;
-    let frag_shader_module = create_shader_module(&device, frag)?;
-
-    let vert_stage = vk::PipelineShaderStageCreateInfo::default()
-        .stage(vk::ShaderStageFlags::VERTEX)
-        .module(vert_shader_module)
-        .name(SHADER_MAIN);
-
-    let frag_stage = vk::PipelineShaderStageCreateInfo::default()
-        .stage(vk::ShaderStageFlags::FRAGMENT)
-        .module(frag_shader_module)
-        .name(SHADER_MAIN);
-
-    let vertex_input_state = vk::PipelineVertexInputStateCreateInfo::default();
-
-    let input_assembly_state = vk::PipelineInputAssemblyStateCreateInfo::default()
-        .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
-        .primitive_restart_enable(false);
-
-    // for future reference
-    // let dynamic_states = [
-    //     vk::DynamicState::VIEWPORT,
-    //     vk::DynamicState::SCISSOR,
-    // ];
-    //
-    // let dynamic_state = vk::PipelineDynamicStateCreateInfo::default()
-    //     .dynamic_states(&dynamic_states);
-
-    let viewport = vk::Viewport::default()
-        .x(0.0)
-        .y(0.0)
-        .width(swapchain_data.extent.width as f32)
-        .height(swapchain_data.extent.height as f32)
-        .min_depth(0.0)
-        .max_depth(0.0);
-
-    let scissor = vk::Rect2D::default()
-        .offset(vk::Offset2D { x: 0, y: 0})
-        .extent(swapchain_data.extent);
-
-    let viewports = &[viewport];
-    let scissors = &[scissor];
-    let viewport_state = vk::PipelineViewportStateCreateInfo::default()
-        .viewports(viewports)
-        .scissors(scissors);
-
-    let rasterizer_state = vk::PipelineRasterizationStateCreateInfo::default()
-        .depth_bias_enable(true)
-        .rasterizer_discard_enable(false)
-        .polygon_mode(vk::PolygonMode::FILL)
-        .line_width(1.0)
-        .cull_mode(vk::CullModeFlags::BACK)
-        .front_face(vk::FrontFace::CLOCKWISE)
-        .depth_bias_enable(false);
-
-    let multisample_state = vk::PipelineMultisampleStateCreateInfo::default()
-        .sample_shading_enable(false)
-        .rasterization_samples(vk::SampleCountFlags::TYPE_1);
-
-    let color_blend_attachment_state = vk::PipelineColorBlendAttachmentState::default()
-        .color_write_mask(vk::ColorComponentFlags::RGBA)
-        .blend_enable(false);
-
-    let color_blend_attachments = [color_blend_attachment_state];
-
-    let color_blend_state = vk::PipelineColorBlendStateCreateInfo::default()
-        .logic_op_enable(false)
-        .attachments(&color_blend_attachments);
-
-    let pipeline_layout_info = vk::PipelineLayoutCreateInfo::default();
-
-    let pipeline_layout = unsafe { device.create_pipeline_layout(&pipeline_layout_info, None)? };
-
-    let stages = &[vert_stage, frag_stage];
-    let pipeline_info = vk::GraphicsPipelineCreateInfo::default()
-        .stages(stages)
-        .vertex_input_state(&vertex_input_state)
-        .input_assembly_state(&input_assembly_state)
-        .viewport_state(&viewport_state)
-        .rasterization_state(&rasterizer_state)
-        .multisample_state(&multisample_state)
-        .color_blend_state(&color_blend_state)
-        .layout(pipeline_layout)
-        .render_pass(*render_pass)
-        .subpass(0);
-
-    let pipeline = unsafe { device.create_graphics_pipelines(vk::PipelineCache::null(), &[pipeline_info], None).unwrap()[0] };
-
-    unsafe {
-        device.destroy_shader_module(vert_shader_module, None);
-        device.destroy_shader_module(frag_shader_module, None);
-    }
-
-    Ok(
-        data::PipelineData {
-            pipeline,
-            layout: pipeline_layout,
+    // a shader that doesn't match the layout, vertex input or the other stages is caught here
+    // rather than by the validation layers, e.g. when hot reloading adds a binding the descriptor
+    // sets were not made with
+    (|| -> Result<()> {
+        let mut previous: Option<EntryPoint> = None;
+        for (stage, bytecode) in shaders {
+            let reflection = bytecode.reflect()?;
+            let entry_point = reflection.entry_point(SHADER_MAIN.to_str()?, *stage)?;
+            layout.check(*stage, &reflection)?;
+
+            match &previous {
+                Some(previous) => entry_point.check_inputs_from(previous)?,
+                None => entry_point.check_vertex_input(&vertex_layout.attributes)?,
+            }
+            previous = Some(entry_point.clone());
         }
-    )
-}
-
-fn create_shader_module(
-    device: &Device,
-    bytecode: &[u8],
-) -> Result<vk::ShaderModule> {
-    let bytecode = Bytecode::from(bytecode)?;
 
-    let info = vk::ShaderModuleCreateInfo::default()
-        .code(bytecode.code());
+        Ok(())
+    })().map_err(Error::Shader)?;
 
-    let shader_module = unsafe { device.create_shader_module(&info, None)? };
+    // only needed until the pipeline is built
+    let shader_modules = shaders
+        .iter()
+        .map(|(stage, bytecode)| Ok((*stage, pipeline::create_shader_module(device, bytecode)?)))
+        .collect::<Result<Vec<_>>>()?;
 
-    Ok(shader_module)
+    shader_modules
+        .iter()
+        .fold(GraphicsPipelineBuilder::new(), |builder, (stage, module)| builder.stage(*stage, **module))
+        .vertex_layout(vertex_layout.clone())
+        .extent(target.extent())
+        .samples(samples)
+        .depth(*depth_config)
+        .set_layouts(&layout.set_layout_handles())
+        .push_constant_ranges(&layout.push_constant_ranges)
+        .cache(*pipeline_cache.cache)
+        .build(device, *render_pass)
 }
 
 fn create_framebuffers(
     device: &Device,
-    swapchain_data: &data::SwapchainData,
+    target: &data::RenderTarget,
+    depth: &AttachmentImage,
+    msaa_color: Option<&AttachmentImage>,
     render_pass: &vk::RenderPass,
-) -> Result<Vec<vk::Framebuffer>> {
-    Ok(swapchain_data.image_views
-        .iter()
+) -> Result<Vec<Owned<vk::Framebuffer>>> {
+    (0..target.image_count())
         .map(|i| {
-            let attachments = &[*i];
+            let image_view = target.image_view(i);
+
+            // same order as the render pass attachments
+            let attachments = match msaa_color {
+                Some(msaa_color) => vec![*msaa_color.view, *depth.view, image_view],
+                None => vec![image_view, *depth.view],
+            };
             let framebuffer_create_info = vk::FramebufferCreateInfo::default()
                 .render_pass(*render_pass)
-                .attachments(attachments)
-                .width(swapchain_data.extent.width)
-                .height(swapchain_data.extent.height)
+                .attachments(&attachments)
+                .width(target.extent().width)
+                .height(target.extent().height)
                 .layers(1);
 
-            unsafe { device.create_framebuffer(&framebuffer_create_info, None) }
-        }).collect::<Result<Vec<_>, _>>()?
-    )
+            unsafe { Ok(Owned::new(device, device.create_framebuffer(&framebuffer_create_info, None)?)) }
+        })
+        .collect()
 }
 
 fn create_command_pool(
     queue_data: &data::QueueData,
     device: &Device,
-) -> Result<vk::CommandPool> {
+) -> Result<Owned<vk::CommandPool>> {
     let command_pool_info = vk::CommandPoolCreateInfo::default()
         .queue_family_index(queue_data.family_indices.graphics);
 
-    unsafe { Ok(device.create_command_pool(&command_pool_info, None)?) }
+    unsafe { Ok(Owned::new(device, device.create_command_pool(&command_pool_info, None)?)) }
 }
 
-fn create_command_buffers(
-    device: &Device,
-    swapchain_data: &data::SwapchainData,
-    render_pass: &vk::RenderPass,
-    pipeline: &vk::Pipeline,
-    framebuffers: &Vec<vk::Framebuffer>,
-    command_pool: &vk::CommandPool,
-) -> Result<Vec<vk::CommandBuffer>> {
-    let allocate_info = vk::CommandBufferAllocateInfo::default()
-        .command_pool(*command_pool)
-        .level(vk::CommandBufferLevel::PRIMARY)
-        .command_buffer_count(framebuffers.len() as u32);
-
-    let command_buffers = unsafe { device.allocate_command_buffers(&allocate_info)? };
-
-    for (i, command_buffer) in command_buffers.iter().enumerate() {
-        let begin_info = vk::CommandBufferBeginInfo::default();
-
-        unsafe { device.begin_command_buffer(*command_buffer, &begin_info)? };
-
-        let render_area = vk::Rect2D::default()
-            .offset(vk::Offset2D::default())
-            .extent(swapchain_data.extent);
-
-        let color_clear_value = vk::ClearValue {
-            color: vk::ClearColorValue {
-                float32: [0.0, 0.0, 0.0, 1.0],
-            },
-        };
+// reflects the scene shaders for the layouts the pipeline and descriptor sets are made with
+fn create_scene_layout(device: &Device, shaders: &[(vk::ShaderStageFlags, Bytecode)]) -> Result<ReflectedLayout> {
+    let stages = shaders
+        .iter()
+        .map(|(stage, bytecode)| Ok((*stage, bytecode.reflect()?)))
+        .collect::<Result<Vec<_>>>()
+        .map_err(Error::Shader)?;
 
-        let clear_values = &[color_clear_value];
-        let pass_begin_info = vk::RenderPassBeginInfo::default()
-            .render_pass(*render_pass)
-            .framebuffer(framebuffers[i])
-            .render_area(render_area)
-            .clear_values(clear_values);
+    let layout = ReflectedLayout::create(device, &stages)?;
 
-        unsafe {
-            device.cmd_begin_render_pass(*command_buffer, &pass_begin_info, vk::SubpassContents::INLINE);
-            device.cmd_bind_pipeline(*command_buffer, vk::PipelineBindPoint::GRAPHICS, *pipeline);
-            device.cmd_draw(*command_buffer, 3, 1, 0, 0);
-            device.cmd_end_render_pass(*command_buffer);
-            device.end_command_buffer(*command_buffer)?;
-        };
+    // the scene's descriptors are all written into set 0
+    if layout.set_layouts.len() != 1 {
+        return Err(Error::Shader(anyhow!("Scene shaders must use exactly one descriptor set, not {}.", layout.sets.len())).into());
     }
 
-    Ok(command_buffers)
+    Ok(layout)
 }
 
-fn create_sync_objects(
+unsafe fn write_texture_descriptors(device: &Device, set: vk::DescriptorSet, texture: &Texture) {
+    descriptor::write_image(device, set, TEXTURE_BINDING, vk::DescriptorType::SAMPLED_IMAGE, texture.descriptor_info());
+    descriptor::write_image(device, set, SAMPLER_BINDING, vk::DescriptorType::SAMPLER, texture.descriptor_info());
+}
+
+// the frame ring. command pools are short-lived, since everything allocated from them is thrown
+// away each frame, and each descriptor set points at its own frame's uniform buffer
+fn create_frames(
     device: &Device,
-    swapchain_data: &data::SwapchainData,
-) -> Result<SyncObjects> {
+    allocator: &mut Allocator,
+    descriptor_allocator: &mut DescriptorAllocator,
+    queue_data: &data::QueueData,
+    layout: vk::DescriptorSetLayout,
+    texture: &Texture,
+    count: usize,
+) -> Result<Vec<FrameContext>> {
+    let command_pool_info = vk::CommandPoolCreateInfo::default()
+        .flags(vk::CommandPoolCreateFlags::TRANSIENT)
+        .queue_family_index(queue_data.family_indices.graphics);
     let semaphore_info = vk::SemaphoreCreateInfo::default();
     let fence_info = vk::FenceCreateInfo::default()
         .flags(vk::FenceCreateFlags::SIGNALED);
+    let uniform_size = std::mem::size_of::<UniformBufferObject>() as vk::DeviceSize;
+
+    (0..count)
+        .map(|_| unsafe {
+            let command_pool = Owned::new(device, device.create_command_pool(&command_pool_info, None)?);
+
+            let allocate_info = vk::CommandBufferAllocateInfo::default()
+                .command_pool(*command_pool)
+                .level(vk::CommandBufferLevel::PRIMARY)
+                .command_buffer_count(1);
+            let command_buffer = device.allocate_command_buffers(&allocate_info)?[0];
+
+            let uniform_buffer = BufferData::create(allocator, uniform_size, vk::BufferUsageFlags::UNIFORM_BUFFER, MemoryUsage::CPU_TO_GPU)?;
+
+            let descriptor_set = descriptor_allocator.allocate(device, layout)?;
+            descriptor::write_uniform_buffer(device, descriptor_set, UNIFORM_BINDING, *uniform_buffer.buffer, uniform_buffer.size);
+            write_texture_descriptors(device, descriptor_set, texture);
+
+            Ok(
+                FrameContext {
+                    command_buffer,
+                    command_pool,
+                    image_available: Owned::new(device, device.create_semaphore(&semaphore_info, None)?),
+                    render_finished: Owned::new(device, device.create_semaphore(&semaphore_info, None)?),
+                    in_flight: Owned::new(device, device.create_fence(&fence_info, None)?),
+                    uniform_buffer,
+                    descriptor_set,
+                    stale_descriptor_set: false,
+                }
+            )
+        })
+        .collect()
+}
 
-    let mut image_available_semaphores = vec![];
-    let mut render_finished_semaphores = vec![];
-    let mut in_flight_fences = vec![];
-    
+// records and submits a one-off command buffer, waiting for it to finish
+unsafe fn single_time_commands<F: FnOnce(vk::CommandBuffer)>(
+    device: &Device,
+    queue: vk::Queue,
+    command_pool: vk::CommandPool,
+    record: F,
+) -> Result<()> {
+    let allocate_info = vk::CommandBufferAllocateInfo::default()
+        .command_pool(command_pool)
+        .level(vk::CommandBufferLevel::PRIMARY)
+        .command_buffer_count(1);
 
-    unsafe {
-        for _ in 0..MAX_FRAMES_IN_FLIGHT {
-            image_available_semaphores.push(device.create_semaphore(&semaphore_info, None)?);
-            render_finished_semaphores.push(device.create_semaphore(&semaphore_info, None)?);
-            in_flight_fences.push(device.create_fence(&fence_info, None)?);
-        }
-    }
+    let command_buffer = device.allocate_command_buffers(&allocate_info)?[0];
 
-    let images_in_flight = swapchain_data.images
-        .iter()
-        .map(|_| vk::Fence::null())
-        .collect();
+    let begin_info = vk::CommandBufferBeginInfo::default()
+        .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
 
-    Ok(
-        data::SyncObjects {
-            image_available_semaphores,
-            render_finished_semaphores,
-            in_flight_fences,
-            images_in_flight,
-        }
-    )
+    let result = (|| -> Result<()> {
+        device.begin_command_buffer(command_buffer, &begin_info)?;
+        record(command_buffer);
+        device.end_command_buffer(command_buffer)?;
+
+        let command_buffers = &[command_buffer];
+        let submit_info = vk::SubmitInfo::default()
+            .command_buffers(command_buffers);
+
+        device.queue_submit(queue, &[submit_info], vk::Fence::null())?;
+        device.queue_wait_idle(queue)?;
+
+        Ok(())
+    })();
+
+    device.free_command_buffers(command_pool, &[command_buffer]);
+
+    result
 }
 
 /*
  * Other
  */
 
-// debug message callback
-pub extern "system" fn debug_callback(
+/// Logs validation messages, errors and warnings as such and the rest at debug or trace.
+///
+/// # Safety
+/// Only for vulkan to call, with `data` pointing to valid callback data.
+pub unsafe extern "system" fn debug_callback(
     severity: vk::DebugUtilsMessageSeverityFlagsEXT,
     type_: vk::DebugUtilsMessageTypeFlagsEXT,
     data: *const vk::DebugUtilsMessengerCallbackDataEXT,
     _: *mut c_void,
 ) -> vk::Bool32 {
-    let data = unsafe { *data };
-    let message = unsafe { CStr::from_ptr(data.p_message) }.to_string_lossy();
+    let data = *data;
+    let message = CStr::from_ptr(data.p_message).to_string_lossy();
 
     if severity >= vk::DebugUtilsMessageSeverityFlagsEXT::ERROR {
         error!("({:?}) {}", type_, message);
@@ -0,0 +1,129 @@
+use std::{fmt, ops::Deref};
+
+use ash::{vk, Device, Instance};
+
+// a handle destroyed through the device that created it
+pub trait DeviceObject: Copy {
+    /// Destroys the handle.
+    ///
+    /// # Safety
+    /// `self` must have been created from `device` and no longer be in use by the gpu. It is
+    /// invalid afterwards.
+    unsafe fn destroy(self, device: &Device);
+}
+
+macro_rules! device_objects {
+    ($($ty:ty => $destroy:ident),* $(,)?) => {
+        $(
+            impl DeviceObject for $ty {
+                unsafe fn destroy(self, device: &Device) {
+                    device.$destroy(self, None);
+                }
+            }
+        )*
+    };
+}
+
+device_objects! {
+    vk::Buffer => destroy_buffer,
+    vk::Image => destroy_image,
+    vk::ImageView => destroy_image_view,
+    vk::Sampler => destroy_sampler,
+    vk::RenderPass => destroy_render_pass,
+    vk::Framebuffer => destroy_framebuffer,
+    vk::Pipeline => destroy_pipeline,
+    vk::PipelineLayout => destroy_pipeline_layout,
+    vk::PipelineCache => destroy_pipeline_cache,
+    vk::DescriptorSetLayout => destroy_descriptor_set_layout,
+    vk::DescriptorPool => destroy_descriptor_pool,
+    vk::CommandPool => destroy_command_pool,
+    vk::ShaderModule => destroy_shader_module,
+    vk::Semaphore => destroy_semaphore,
+    vk::Fence => destroy_fence,
+}
+
+// destroys its handle when dropped. it keeps its own copy of the device's function table,
+// so it must still be dropped before the device itself
+pub struct Owned<T: DeviceObject> {
+    handle: T,
+    device: Device,
+}
+
+impl<T: DeviceObject> Owned<T> {
+    pub fn new(device: &Device, handle: T) -> Self {
+        Self {
+            handle,
+            device: device.clone(),
+        }
+    }
+}
+
+impl<T: DeviceObject> Deref for Owned<T> {
+    type Target = T;
+
+    fn deref(&self) -> &T {
+        &self.handle
+    }
+}
+
+impl<T: DeviceObject + fmt::Debug> fmt::Debug for Owned<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.handle.fmt(f)
+    }
+}
+
+impl<T: DeviceObject> Drop for Owned<T> {
+    fn drop(&mut self) {
+        unsafe { self.handle.destroy(&self.device) };
+    }
+}
+
+// the instance, destroyed once everything created from it has been dropped
+pub struct OwnedInstance(Instance);
+
+impl OwnedInstance {
+    pub fn new(instance: Instance) -> Self {
+        Self(instance)
+    }
+}
+
+impl Deref for OwnedInstance {
+    type Target = Instance;
+
+    fn deref(&self) -> &Instance {
+        &self.0
+    }
+}
+
+impl Drop for OwnedInstance {
+    fn drop(&mut self) {
+        unsafe { self.0.destroy_instance(None) };
+    }
+}
+
+// the logical device, waited on before it is destroyed so nothing is still executing
+pub struct OwnedDevice(Device);
+
+impl OwnedDevice {
+    pub fn new(device: Device) -> Self {
+        Self(device)
+    }
+}
+
+impl Deref for OwnedDevice {
+    type Target = Device;
+
+    fn deref(&self) -> &Device {
+        &self.0
+    }
+}
+
+impl Drop for OwnedDevice {
+    fn drop(&mut self) {
+        unsafe {
+            // a lost device can't be waited on, but still has to be destroyed
+            let _ = self.0.device_wait_idle();
+            self.0.destroy_device(None);
+        }
+    }
+}
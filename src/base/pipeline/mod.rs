@@ -0,0 +1,349 @@
+use std::ffi::{CStr, CString};
+
+use ash::{vk, Device};
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::util::constants::*;
+use crate::util::Bytecode;
+
+use super::buffer::VertexLayout;
+use super::config::DepthConfig;
+use super::data::PipelineData;
+use super::owned::Owned;
+
+// colour blending presets for the builder's attachments
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlendMode {
+    // overwrites the attachment
+    Opaque,
+    // src * src_alpha + dst * (1 - src_alpha)
+    Alpha,
+    // src + dst * (1 - src_alpha), for colours already multiplied by their alpha
+    Premultiplied,
+    // src * src_alpha + dst
+    Additive,
+}
+
+impl BlendMode {
+    pub fn attachment_state(&self) -> vk::PipelineColorBlendAttachmentState {
+        let state = vk::PipelineColorBlendAttachmentState::default()
+            .color_write_mask(vk::ColorComponentFlags::RGBA);
+
+        let (src_color, dst_color) = match self {
+            Self::Opaque => return state.blend_enable(false),
+            Self::Alpha => (vk::BlendFactor::SRC_ALPHA, vk::BlendFactor::ONE_MINUS_SRC_ALPHA),
+            Self::Premultiplied => (vk::BlendFactor::ONE, vk::BlendFactor::ONE_MINUS_SRC_ALPHA),
+            Self::Additive => (vk::BlendFactor::SRC_ALPHA, vk::BlendFactor::ONE),
+        };
+
+        state
+            .blend_enable(true)
+            .src_color_blend_factor(src_color)
+            .dst_color_blend_factor(dst_color)
+            .color_blend_op(vk::BlendOp::ADD)
+            .src_alpha_blend_factor(vk::BlendFactor::ONE)
+            .dst_alpha_blend_factor(vk::BlendFactor::ONE_MINUS_SRC_ALPHA)
+            .alpha_blend_op(vk::BlendOp::ADD)
+    }
+}
+
+// rasterizer state, defaulting to filled, back-face culled, clockwise triangles
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RasterState {
+    pub polygon_mode: vk::PolygonMode,
+    pub cull_mode: vk::CullModeFlags,
+    pub front_face: vk::FrontFace,
+    pub line_width: f32,
+}
+
+impl Default for RasterState {
+    fn default() -> Self {
+        Self {
+            polygon_mode: vk::PolygonMode::FILL,
+            cull_mode: vk::CullModeFlags::BACK,
+            front_face: vk::FrontFace::CLOCKWISE,
+            line_width: 1.0,
+        }
+    }
+}
+
+struct ShaderStage {
+    stage: vk::ShaderStageFlags,
+    module: vk::ShaderModule,
+    entry: CString,
+}
+
+// collects the state of a graphics pipeline and its layout. the shader modules are only
+// borrowed, so they can be destroyed as soon as build returns
+pub struct GraphicsPipelineBuilder {
+    stages: Vec<ShaderStage>,
+    vertex_layout: VertexLayout,
+    topology: vk::PrimitiveTopology,
+    primitive_restart: bool,
+    raster: RasterState,
+    samples: vk::SampleCountFlags,
+    depth: DepthConfig,
+    blend_modes: Vec<BlendMode>,
+    dynamic_states: Vec<vk::DynamicState>,
+    extent: vk::Extent2D,
+    set_layouts: Vec<vk::DescriptorSetLayout>,
+    push_constant_ranges: Vec<vk::PushConstantRange>,
+    subpass: u32,
+    cache: vk::PipelineCache,
+}
+
+impl Default for GraphicsPipelineBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GraphicsPipelineBuilder {
+    pub fn new() -> Self {
+        Self {
+            stages: vec![],
+            vertex_layout: VertexLayout::default(),
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            primitive_restart: false,
+            raster: RasterState::default(),
+            samples: vk::SampleCountFlags::TYPE_1,
+            depth: DepthConfig::default(),
+            blend_modes: vec![BlendMode::Opaque],
+            dynamic_states: vec![],
+            extent: vk::Extent2D::default(),
+            set_layouts: vec![],
+            push_constant_ranges: vec![],
+            subpass: 0,
+            cache: vk::PipelineCache::null(),
+        }
+    }
+
+    // adds a stage with the usual "main" entry point
+    pub fn stage(self, stage: vk::ShaderStageFlags, module: vk::ShaderModule) -> Self {
+        self.stage_with_entry(stage, module, SHADER_MAIN)
+    }
+
+    pub fn stage_with_entry(mut self, stage: vk::ShaderStageFlags, module: vk::ShaderModule, entry: &CStr) -> Self {
+        self.stages.push(ShaderStage { stage, module, entry: entry.to_owned() });
+        self
+    }
+
+    pub fn vertex_layout(mut self, vertex_layout: VertexLayout) -> Self {
+        self.vertex_layout = vertex_layout;
+        self
+    }
+
+    pub fn topology(mut self, topology: vk::PrimitiveTopology) -> Self {
+        self.topology = topology;
+        self
+    }
+
+    pub fn primitive_restart(mut self, enable: bool) -> Self {
+        self.primitive_restart = enable;
+        self
+    }
+
+    pub fn raster(mut self, raster: RasterState) -> Self {
+        self.raster = raster;
+        self
+    }
+
+    pub fn polygon_mode(mut self, polygon_mode: vk::PolygonMode) -> Self {
+        self.raster.polygon_mode = polygon_mode;
+        self
+    }
+
+    pub fn cull_mode(mut self, cull_mode: vk::CullModeFlags) -> Self {
+        self.raster.cull_mode = cull_mode;
+        self
+    }
+
+    pub fn front_face(mut self, front_face: vk::FrontFace) -> Self {
+        self.raster.front_face = front_face;
+        self
+    }
+
+    pub fn samples(mut self, samples: vk::SampleCountFlags) -> Self {
+        self.samples = samples;
+        self
+    }
+
+    pub fn depth(mut self, depth: DepthConfig) -> Self {
+        self.depth = depth;
+        self
+    }
+
+    // one blend mode per colour attachment of the subpass
+    pub fn blend_modes(mut self, blend_modes: &[BlendMode]) -> Self {
+        self.blend_modes = blend_modes.to_vec();
+        self
+    }
+
+    pub fn blend(self, blend_mode: BlendMode) -> Self {
+        self.blend_modes(&[blend_mode])
+    }
+
+    pub fn dynamic_states(mut self, dynamic_states: &[vk::DynamicState]) -> Self {
+        self.dynamic_states = dynamic_states.to_vec();
+        self
+    }
+
+    // size of the fixed viewport and scissor, unused when both are dynamic
+    pub fn extent(mut self, extent: vk::Extent2D) -> Self {
+        self.extent = extent;
+        self
+    }
+
+    pub fn set_layouts(mut self, set_layouts: &[vk::DescriptorSetLayout]) -> Self {
+        self.set_layouts = set_layouts.to_vec();
+        self
+    }
+
+    pub fn push_constant_range(mut self, stages: vk::ShaderStageFlags, offset: u32, size: u32) -> Self {
+        self.push_constant_ranges.push(
+            vk::PushConstantRange::default()
+                .stage_flags(stages)
+                .offset(offset)
+                .size(size)
+        );
+        self
+    }
+
+    pub fn push_constant_ranges(mut self, ranges: &[vk::PushConstantRange]) -> Self {
+        self.push_constant_ranges.extend_from_slice(ranges);
+        self
+    }
+
+    pub fn subpass(mut self, subpass: u32) -> Self {
+        self.subpass = subpass;
+        self
+    }
+
+    pub fn cache(mut self, cache: vk::PipelineCache) -> Self {
+        self.cache = cache;
+        self
+    }
+
+    pub fn build(&self, device: &Device, render_pass: vk::RenderPass) -> Result<PipelineData> {
+        if self.stages.is_empty() {
+            return Err(anyhow!("Pipeline has no shader stages."));
+        }
+
+        let stages = self.stages
+            .iter()
+            .map(|s| vk::PipelineShaderStageCreateInfo::default()
+                .stage(s.stage)
+                .module(s.module)
+                .name(&s.entry))
+            .collect::<Vec<_>>();
+
+        let vertex_input_state = self.vertex_layout.input_state();
+
+        let input_assembly_state = vk::PipelineInputAssemblyStateCreateInfo::default()
+            .topology(self.topology)
+            .primitive_restart_enable(self.primitive_restart);
+
+        let viewport = vk::Viewport::default()
+            .x(0.0)
+            .y(0.0)
+            .width(self.extent.width as f32)
+            .height(self.extent.height as f32)
+            .min_depth(0.0)
+            .max_depth(1.0);
+
+        let scissor = vk::Rect2D::default()
+            .offset(vk::Offset2D { x: 0, y: 0})
+            .extent(self.extent);
+
+        let viewports = &[viewport];
+        let scissors = &[scissor];
+        let mut viewport_state = vk::PipelineViewportStateCreateInfo::default()
+            .viewports(viewports)
+            .scissors(scissors);
+
+        // dynamic viewports and scissors are set when recording, only the count matters here
+        if self.dynamic_states.contains(&vk::DynamicState::VIEWPORT) {
+            viewport_state.p_viewports = std::ptr::null();
+        }
+
+        if self.dynamic_states.contains(&vk::DynamicState::SCISSOR) {
+            viewport_state.p_scissors = std::ptr::null();
+        }
+
+        let rasterizer_state = vk::PipelineRasterizationStateCreateInfo::default()
+            .rasterizer_discard_enable(false)
+            .polygon_mode(self.raster.polygon_mode)
+            .line_width(self.raster.line_width)
+            .cull_mode(self.raster.cull_mode)
+            .front_face(self.raster.front_face)
+            .depth_bias_enable(false);
+
+        let multisample_state = vk::PipelineMultisampleStateCreateInfo::default()
+            .sample_shading_enable(false)
+            .rasterization_samples(self.samples);
+
+        let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::default()
+            .depth_test_enable(self.depth.test)
+            .depth_write_enable(self.depth.write)
+            .depth_compare_op(self.depth.compare_op)
+            .depth_bounds_test_enable(false)
+            .stencil_test_enable(false);
+
+        let color_blend_attachments = self.blend_modes
+            .iter()
+            .map(BlendMode::attachment_state)
+            .collect::<Vec<_>>();
+
+        let color_blend_state = vk::PipelineColorBlendStateCreateInfo::default()
+            .logic_op_enable(false)
+            .attachments(&color_blend_attachments);
+
+        let dynamic_state = vk::PipelineDynamicStateCreateInfo::default()
+            .dynamic_states(&self.dynamic_states);
+
+        let pipeline_layout_info = vk::PipelineLayoutCreateInfo::default()
+            .set_layouts(&self.set_layouts)
+            .push_constant_ranges(&self.push_constant_ranges);
+
+        let pipeline_layout = unsafe { Owned::new(device, device.create_pipeline_layout(&pipeline_layout_info, None)?) };
+
+        let pipeline_info = vk::GraphicsPipelineCreateInfo::default()
+            .stages(&stages)
+            .vertex_input_state(&vertex_input_state)
+            .input_assembly_state(&input_assembly_state)
+            .viewport_state(&viewport_state)
+            .rasterization_state(&rasterizer_state)
+            .multisample_state(&multisample_state)
+            .depth_stencil_state(&depth_stencil_state)
+            .color_blend_state(&color_blend_state)
+            .dynamic_state(&dynamic_state)
+            .layout(*pipeline_layout)
+            .render_pass(render_pass)
+            .subpass(self.subpass);
+
+        let pipeline = match unsafe { device.create_graphics_pipelines(self.cache, &[pipeline_info], None) } {
+            Ok(pipelines) => Owned::new(device, pipelines[0]),
+            Err((_, e)) => return Err(e).context("Failed to create graphics pipeline."),
+        };
+
+        Ok(
+            PipelineData {
+                pipeline,
+                layout: pipeline_layout,
+            }
+        )
+    }
+}
+
+pub fn create_shader_module(
+    device: &Device,
+    bytecode: &Bytecode,
+) -> Result<Owned<vk::ShaderModule>> {
+    let info = vk::ShaderModuleCreateInfo::default()
+        .code(bytecode.code());
+
+    let shader_module = unsafe { Owned::new(device, device.create_shader_module(&info, None)?) };
+
+    Ok(shader_module)
+}
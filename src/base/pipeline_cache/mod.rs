@@ -0,0 +1,227 @@
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+};
+
+use ash::{vk, Device, Instance};
+
+use anyhow::Result;
+
+use log::*;
+
+use super::owned::Owned;
+
+// size of VkPipelineCacheHeaderVersionOne
+const HEADER_SIZE: usize = 16 + vk::UUID_SIZE;
+
+// a vk::PipelineCache backed by a file, so pipelines compiled in one run are reused by the next
+pub struct PipelineCache {
+    pub cache: Owned<vk::PipelineCache>,
+    // where the cache is written back to, None keeps it in memory only
+    pub path: Option<PathBuf>,
+    // bytes of valid data loaded at startup, 0 for a cold cache
+    pub loaded_size: usize,
+}
+
+impl PipelineCache {
+    /// Creates the cache from the file at `path`, starting empty when there is none or its data is
+    /// for another device or driver.
+    ///
+    /// # Safety
+    /// `device` must have been created from `physical_device`, which must belong to `instance`.
+    pub unsafe fn load(
+        instance: &Instance,
+        physical_device: vk::PhysicalDevice,
+        device: &Device,
+        path: Option<&Path>,
+    ) -> Result<Self> {
+        let properties = instance.get_physical_device_properties(physical_device);
+
+        let data = match path {
+            Some(path) => match fs::read(path) {
+                Ok(data) => match validate_header(&data, &properties) {
+                    Ok(()) => data,
+                    Err(reason) => {
+                        info!("Ignoring pipeline cache {}: {}", path.display(), reason);
+                        vec![]
+                    },
+                },
+                // a missing file just means a first run
+                Err(_) => vec![],
+            },
+            None => vec![],
+        };
+
+        let info = vk::PipelineCacheCreateInfo::default()
+            .initial_data(&data);
+
+        let cache = match device.create_pipeline_cache(&info, None) {
+            Ok(cache) => cache,
+            // drivers may still reject data with a matching header, start empty instead
+            Err(e) if !data.is_empty() => {
+                warn!("Driver rejected pipeline cache data ({:?}), starting empty.", e);
+                device.create_pipeline_cache(&vk::PipelineCacheCreateInfo::default(), None)?
+            },
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(
+            Self {
+                cache: Owned::new(device, cache),
+                path: path.map(Path::to_path_buf),
+                loaded_size: data.len(),
+            }
+        )
+    }
+
+    /// Writes the cache back to its file, if it has one.
+    ///
+    /// # Safety
+    /// `device` must be the one the cache was loaded on.
+    pub unsafe fn save(&self, device: &Device) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+
+        let data = device.get_pipeline_cache_data(*self.cache)?;
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        // write then rename, so a crash never leaves a truncated cache behind
+        let temp_path = path.with_extension("tmp");
+        fs::write(&temp_path, &data)?;
+        fs::rename(&temp_path, path)?;
+
+        info!("Saved {} byte pipeline cache to {}.", data.len(), path.display());
+
+        Ok(())
+    }
+}
+
+// the header ties the data to one driver and device, anything else must not be handed to vulkan
+fn validate_header(data: &[u8], properties: &vk::PhysicalDeviceProperties) -> Result<(), String> {
+    if data.len() < HEADER_SIZE {
+        return Err(format!("only {} bytes, too short for a header", data.len()));
+    }
+
+    let read_u32 = |offset: usize| u32::from_le_bytes(data[offset..offset + 4].try_into().unwrap());
+
+    let header_size = read_u32(0);
+    let header_version = read_u32(4);
+    let vendor_id = read_u32(8);
+    let device_id = read_u32(12);
+    let uuid = &data[16..HEADER_SIZE];
+
+    if (header_size as usize) < HEADER_SIZE || header_version != vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32 {
+        return Err(format!("unknown header version {} of size {}", header_version, header_size));
+    }
+
+    if vendor_id != properties.vendor_id || device_id != properties.device_id {
+        return Err(format!(
+            "made for vendor {:#x} device {:#x}, not vendor {:#x} device {:#x}",
+            vendor_id, device_id, properties.vendor_id, properties.device_id,
+        ));
+    }
+
+    if uuid != properties.pipeline_cache_uuid {
+        return Err("pipeline cache UUID differs, the driver has probably changed".to_string());
+    }
+
+    Ok(())
+}
+
+// <user cache dir>/vulkan-testing/pipeline_cache.bin, None if there is no home to put it in
+pub fn default_path() -> Option<PathBuf> {
+    let cache_dir = if cfg!(target_os = "windows") {
+        env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        env::var_os("HOME").map(|h| PathBuf::from(h).join("Library").join("Caches"))
+    } else {
+        env::var_os("XDG_CACHE_HOME")
+            .filter(|d| !d.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| env::var_os("HOME").map(|h| PathBuf::from(h).join(".cache")))
+    };
+
+    cache_dir.map(|d| d.join(env!("CARGO_PKG_NAME")).join("pipeline_cache.bin"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UUID: [u8; vk::UUID_SIZE] = [7; vk::UUID_SIZE];
+
+    fn properties() -> vk::PhysicalDeviceProperties {
+        vk::PhysicalDeviceProperties {
+            vendor_id: 0x10de,
+            device_id: 0x2206,
+            pipeline_cache_uuid: UUID,
+            ..Default::default()
+        }
+    }
+
+    // a header as the driver writes it, followed by some cache data
+    fn header(version: u32, vendor_id: u32, device_id: u32, uuid: [u8; vk::UUID_SIZE]) -> Vec<u8> {
+        let mut data = vec![];
+        data.extend_from_slice(&(HEADER_SIZE as u32).to_le_bytes());
+        data.extend_from_slice(&version.to_le_bytes());
+        data.extend_from_slice(&vendor_id.to_le_bytes());
+        data.extend_from_slice(&device_id.to_le_bytes());
+        data.extend_from_slice(&uuid);
+        data.extend_from_slice(&[0xab; 64]);
+        data
+    }
+
+    fn valid() -> Vec<u8> {
+        header(vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32, 0x10de, 0x2206, UUID)
+    }
+
+    #[test]
+    fn matching_header_is_accepted() {
+        assert_eq!(validate_header(&valid(), &properties()), Ok(()));
+
+        // nothing but the header is needed
+        assert_eq!(validate_header(&valid()[..HEADER_SIZE], &properties()), Ok(()));
+    }
+
+    #[test]
+    fn short_data_is_rejected() {
+        assert!(validate_header(&[], &properties()).is_err());
+        assert!(validate_header(&valid()[..HEADER_SIZE - 1], &properties()).is_err());
+    }
+
+    #[test]
+    fn unknown_header_version_is_rejected() {
+        let data = header(2, 0x10de, 0x2206, UUID);
+        assert!(validate_header(&data, &properties()).is_err());
+
+        // a header size smaller than version one's
+        let mut data = valid();
+        data[0..4].copy_from_slice(&16u32.to_le_bytes());
+        assert!(validate_header(&data, &properties()).is_err());
+    }
+
+    #[test]
+    fn other_vendor_is_rejected() {
+        let data = header(vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32, 0x1002, 0x2206, UUID);
+        assert!(validate_header(&data, &properties()).is_err());
+    }
+
+    #[test]
+    fn other_device_is_rejected() {
+        let data = header(vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32, 0x10de, 0x2204, UUID);
+        assert!(validate_header(&data, &properties()).is_err());
+    }
+
+    #[test]
+    fn other_driver_is_rejected() {
+        let mut uuid = UUID;
+        uuid[vk::UUID_SIZE - 1] = 8;
+
+        let data = header(vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32, 0x10de, 0x2206, uuid);
+        assert!(validate_header(&data, &properties()).is_err());
+    }
+}
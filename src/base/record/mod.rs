@@ -0,0 +1,112 @@
+use ash::{vk, Device};
+
+use anyhow::Result;
+
+use super::buffer::{Mesh, TexturedVertex};
+use super::data::PipelineData;
+
+// records a frame's commands between vkBeginCommandBuffer and vkEndCommandBuffer,
+// it must begin and end the render pass itself
+pub type Recorder = Box<dyn FnMut(&RecordContext) -> Result<()>>;
+
+// everything a recorder needs for the frame being recorded
+pub struct RecordContext<'a> {
+    pub device: &'a Device,
+    pub command_buffer: vk::CommandBuffer,
+    pub render_pass: vk::RenderPass,
+    pub framebuffer: vk::Framebuffer,
+    pub extent: vk::Extent2D,
+    // frame in flight and target image being recorded for
+    pub frame: usize,
+    pub image_index: usize,
+    pub pipeline_data: &'a PipelineData,
+    // the frame's uniform buffer and the current texture
+    pub descriptor_set: vk::DescriptorSet,
+    pub mesh: &'a Mesh<TexturedVertex>,
+}
+
+impl RecordContext<'_> {
+    /// Begins the render pass over the whole framebuffer, clearing colour to black and depth to 1.
+    ///
+    /// # Safety
+    /// Only from inside a recorder, outside the render pass.
+    pub unsafe fn begin_render_pass(&self) {
+        self.begin_render_pass_with(&[
+            vk::ClearValue {
+                color: vk::ClearColorValue {
+                    float32: [0.0, 0.0, 0.0, 1.0],
+                },
+            },
+            vk::ClearValue {
+                depth_stencil: vk::ClearDepthStencilValue {
+                    depth: 1.0,
+                    stencil: 0,
+                },
+            },
+        ]);
+    }
+
+    /// Begins the render pass with `clear_values`, which are in render pass attachment order,
+    /// colour then depth.
+    ///
+    /// # Safety
+    /// Only from inside a recorder, outside the render pass, with a clear value for every
+    /// attachment that is cleared.
+    pub unsafe fn begin_render_pass_with(&self, clear_values: &[vk::ClearValue]) {
+        let render_area = vk::Rect2D::default()
+            .offset(vk::Offset2D::default())
+            .extent(self.extent);
+
+        let pass_begin_info = vk::RenderPassBeginInfo::default()
+            .render_pass(self.render_pass)
+            .framebuffer(self.framebuffer)
+            .render_area(render_area)
+            .clear_values(clear_values);
+
+        self.device.cmd_begin_render_pass(self.command_buffer, &pass_begin_info, vk::SubpassContents::INLINE);
+    }
+
+    /// Ends the render pass.
+    ///
+    /// # Safety
+    /// Only after beginning the render pass in the same recorder call.
+    pub unsafe fn end_render_pass(&self) {
+        self.device.cmd_end_render_pass(self.command_buffer);
+    }
+
+    /// Binds the scene pipeline and descriptor set.
+    ///
+    /// # Safety
+    /// Only from inside a recorder.
+    pub unsafe fn bind_pipeline(&self) {
+        self.device.cmd_bind_pipeline(self.command_buffer, vk::PipelineBindPoint::GRAPHICS, *self.pipeline_data.pipeline);
+        self.device.cmd_bind_descriptor_sets(
+            self.command_buffer,
+            vk::PipelineBindPoint::GRAPHICS,
+            *self.pipeline_data.layout,
+            0,
+            &[self.descriptor_set],
+            &[],
+        );
+    }
+
+    /// Draws the app's mesh with the scene pipeline.
+    ///
+    /// # Safety
+    /// Only from inside a recorder, between beginning and ending the render pass.
+    pub unsafe fn draw_scene(&self) {
+        self.bind_pipeline();
+        self.mesh.draw(self.device, self.command_buffer);
+    }
+}
+
+// what gets recorded when no recorder is set
+pub fn record_default(context: &RecordContext) -> Result<()> {
+    unsafe {
+        context.begin_render_pass();
+        context.draw_scene();
+        context.end_render_pass();
+    }
+
+    Ok(())
+}
@@ -0,0 +1,84 @@
+// shared with build.rs, so this only depends on std and naga
+
+use std::{fs, path::Path};
+
+use naga::{
+    back::spv,
+    front::{glsl, wgsl},
+    valid::{Capabilities, ValidationFlags, Validator},
+    ShaderStage,
+};
+
+// compiles a glsl (.vert, .frag, .comp) or wgsl file to SPIR-V. errors are formatted
+// with the file, line and a snippet of the source
+pub fn compile_file(path: &Path) -> Result<Vec<u32>, String> {
+    let source = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+
+    compile_source(&source, path)
+}
+
+pub fn compile_source(source: &str, path: &Path) -> Result<Vec<u32>, String> {
+    let path_name = path.display().to_string();
+    let extension = path.extension().and_then(|e| e.to_str()).unwrap_or_default();
+
+    let (module, stage) = match extension {
+        "wgsl" => {
+            let module = wgsl::parse_str(source)
+                .map_err(|e| e.emit_to_string_with_path(source, &path_name))?;
+            (module, None)
+        },
+        _ => {
+            let stage = glsl_stage(extension)
+                .ok_or_else(|| format!("{}: unknown shader extension", path_name))?;
+            let module = glsl::Frontend::default()
+                .parse(&glsl::Options::from(stage), source)
+                .map_err(|e| e.emit_to_string_with_path(source, &path_name))?;
+            (module, Some(stage))
+        },
+    };
+
+    let info = Validator::new(ValidationFlags::all(), Capabilities::all())
+        .validate(&module)
+        .map_err(|e| e.emit_to_string_with_path(source, &path_name))?;
+
+    let mut options = spv::Options::default();
+    options.flags.remove(spv::WriterFlags::DEBUG);
+
+    // glsl written for vulkan is already in its clip space, only wgsl's upward y needs flipping
+    if stage.is_some() {
+        options.flags.remove(spv::WriterFlags::ADJUST_COORDINATE_SPACE);
+    }
+
+    // glsl has a single "main" entry point, wgsl keeps all of its entry points
+    let pipeline_options = stage.map(|shader_stage| spv::PipelineOptions {
+        shader_stage,
+        entry_point: "main".to_string(),
+    });
+
+    spv::write_vec(&module, &info, &options, pipeline_options.as_ref())
+        .map_err(|e| format!("{}: {}", path_name, e))
+}
+
+pub fn is_shader(path: &Path) -> bool {
+    matches!(path.extension().and_then(|e| e.to_str()), Some("wgsl" | "vert" | "frag" | "comp"))
+}
+
+// shader.vert -> SHADER_VERT
+pub fn constant_name(file_name: &str) -> String {
+    file_name
+        .chars()
+        .map(|c| match c.is_ascii_alphanumeric() {
+            true => c.to_ascii_uppercase(),
+            false => '_',
+        })
+        .collect()
+}
+
+fn glsl_stage(extension: &str) -> Option<ShaderStage> {
+    match extension {
+        "vert" => Some(ShaderStage::Vertex),
+        "frag" => Some(ShaderStage::Fragment),
+        "comp" => Some(ShaderStage::Compute),
+        _ => None,
+    }
+}
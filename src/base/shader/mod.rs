@@ -0,0 +1,115 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+use ash::vk;
+
+use anyhow::{anyhow, Context, Result};
+
+use log::*;
+
+use crate::util::{spirv, Bytecode};
+
+// how often the watcher looks at the files, stat-ing every frame is wasteful
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+pub mod compile;
+
+// Bytecode constants for every shader in src/shaders, compiled by build.rs
+pub mod compiled {
+    use crate::util::Bytecode;
+
+    include!(concat!(env!("OUT_DIR"), "/shaders.rs"));
+}
+
+// a shader embedded in the binary at build time. debug builds recompile the source in
+// src/shaders when it changes, so it can be edited without rebuilding
+#[derive(Debug, Clone)]
+pub struct ShaderFile {
+    pub name: &'static str,
+    pub stage: vk::ShaderStageFlags,
+    pub embedded: Bytecode,
+}
+
+pub const SCENE_VERT: ShaderFile = ShaderFile {
+    name: "shader.vert",
+    stage: vk::ShaderStageFlags::VERTEX,
+    embedded: compiled::SHADER_VERT,
+};
+
+pub const SCENE_FRAG: ShaderFile = ShaderFile {
+    name: "shader.frag",
+    stage: vk::ShaderStageFlags::FRAGMENT,
+    embedded: compiled::SHADER_FRAG,
+};
+
+// everything the scene pipeline is built from
+pub const SCENE_SHADERS: &[ShaderFile] = &[SCENE_VERT, SCENE_FRAG];
+
+impl ShaderFile {
+    pub fn path(&self) -> PathBuf {
+        shader_dir().join(self.name)
+    }
+
+    // compiles the source on disk, only done when hot reloading
+    pub fn load(&self) -> Result<Bytecode> {
+        // running away from the source tree, the embedded copy is all there is
+        let bytecode = match !cfg!(debug_assertions) || !shader_dir().exists() {
+            true => self.embedded.clone(),
+            false => compile::compile_file(&self.path())
+                .map(Bytecode::from_words)
+                .map_err(|e| anyhow!("Failed to compile shader:\n{}", e))?,
+        };
+
+        // a bad module should fail here with a clear message rather than somewhere in the driver
+        spirv::check_header(bytecode.code()).with_context(|| format!("{} is not valid SPIR-V", self.name))?;
+
+        Ok(bytecode)
+    }
+}
+
+pub fn shader_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("src").join("shaders")
+}
+
+// polls modification times of shader sources
+pub struct ShaderWatcher {
+    files: Vec<(&'static str, Option<SystemTime>)>,
+    last_poll: Instant,
+}
+
+impl ShaderWatcher {
+    pub fn new(files: &[ShaderFile]) -> Self {
+        Self {
+            files: files.iter().map(|f| (f.name, modified(&f.path()))).collect(),
+            last_poll: Instant::now(),
+        }
+    }
+
+    // names of the files that changed since the last call
+    pub fn poll(&mut self) -> Vec<&'static str> {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return vec![];
+        }
+        self.last_poll = Instant::now();
+
+        let mut changed = vec![];
+        for (name, last_modified) in self.files.iter_mut() {
+            let modified = modified(&shader_dir().join(*name));
+
+            if modified.is_some() && modified != *last_modified {
+                debug!("Shader {} changed.", name);
+                *last_modified = modified;
+                changed.push(*name);
+            }
+        }
+
+        changed
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
@@ -0,0 +1,240 @@
+use std::path::Path;
+
+use ash::{vk, Device};
+
+use anyhow::{anyhow, Result};
+
+use super::allocator::{Allocation, Allocator, MemoryUsage};
+use super::buffer::BufferData;
+use super::owned::Owned;
+
+// textures are always uploaded as srgb rgba8
+pub const TEXTURE_FORMAT: vk::Format = vk::Format::R8G8B8A8_SRGB;
+
+pub struct Texture {
+    pub sampler: Owned<vk::Sampler>,
+    pub view: Owned<vk::ImageView>,
+    pub image: Owned<vk::Image>,
+    pub allocation: Allocation,
+    pub extent: vk::Extent2D,
+}
+
+impl Texture {
+    /// Decodes a png or jpeg from disk.
+    ///
+    /// # Safety
+    /// `queue` and `command_pool` must belong to `device`, the device `allocator` was made for.
+    /// This blocks until the upload is done.
+    pub unsafe fn from_file<P: AsRef<Path>>(
+        device: &Device,
+        allocator: &mut Allocator,
+        queue: vk::Queue,
+        command_pool: vk::CommandPool,
+        path: P,
+    ) -> Result<Self> {
+        let path = path.as_ref();
+        let decoded = image::open(path)
+            .map_err(|e| anyhow!("Failed to load texture {}: {}", path.display(), e))?
+            .into_rgba8();
+
+        let (width, height) = decoded.dimensions();
+
+        Self::from_rgba8(device, allocator, queue, command_pool, width, height, decoded.as_raw())
+    }
+
+    /// A 1x1 texture of a single colour, used when nothing else is bound.
+    ///
+    /// # Safety
+    /// Both `queue` and `command_pool` must come from `device`, as must `allocator`. The upload is
+    /// waited for.
+    pub unsafe fn solid(
+        device: &Device,
+        allocator: &mut Allocator,
+        queue: vk::Queue,
+        command_pool: vk::CommandPool,
+        rgba: [u8; 4],
+    ) -> Result<Self> {
+        Self::from_rgba8(device, allocator, queue, command_pool, 1, 1, &rgba)
+    }
+
+    /// Uploads tightly packed rgba8 pixels, `width` by `height`.
+    ///
+    /// # Safety
+    /// `queue` and `command_pool` must belong to `device`, the device `allocator` was made for.
+    /// This blocks until the upload is done.
+    pub unsafe fn from_rgba8(
+        device: &Device,
+        allocator: &mut Allocator,
+        queue: vk::Queue,
+        command_pool: vk::CommandPool,
+        width: u32,
+        height: u32,
+        pixels: &[u8],
+    ) -> Result<Self> {
+        if width == 0 || height == 0 {
+            return Err(anyhow!("Texture has no pixels."));
+        }
+
+        // checked, as a large enough texture overflows u32 and would wrap to a size that matches
+        let size = (width as usize)
+            .checked_mul(height as usize)
+            .and_then(|n| n.checked_mul(4))
+            .ok_or_else(|| anyhow!("A {}x{} texture is too large.", width, height))?;
+
+        if pixels.len() != size {
+            return Err(anyhow!("Expected {} bytes of rgba8 for a {}x{} texture, got {}.", size, width, height, pixels.len()));
+        }
+
+        let extent = vk::Extent2D { width, height };
+
+        let image_info = vk::ImageCreateInfo::default()
+            .image_type(vk::ImageType::TYPE_2D)
+            .format(TEXTURE_FORMAT)
+            .extent(extent.into())
+            .mip_levels(1)
+            .array_layers(1)
+            .samples(vk::SampleCountFlags::TYPE_1)
+            .tiling(vk::ImageTiling::OPTIMAL)
+            .usage(vk::ImageUsageFlags::TRANSFER_DST | vk::ImageUsageFlags::SAMPLED)
+            .sharing_mode(vk::SharingMode::EXCLUSIVE)
+            .initial_layout(vk::ImageLayout::UNDEFINED);
+
+        let (image, allocation) = allocator.create_image(&image_info, MemoryUsage::GPU_ONLY)?;
+
+        upload_pixels(device, allocator, queue, command_pool, *image, extent, pixels)?;
+        let view = super::create_image_view(device, *image, TEXTURE_FORMAT, vk::ImageAspectFlags::COLOR)?;
+        let sampler = create_sampler(device, vk::Filter::LINEAR, vk::SamplerAddressMode::REPEAT)?;
+
+        Ok(
+            Self {
+                sampler,
+                view,
+                image,
+                allocation,
+                extent,
+            }
+        )
+    }
+
+    pub fn descriptor_info(&self) -> vk::DescriptorImageInfo {
+        vk::DescriptorImageInfo::default()
+            .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+            .image_view(*self.view)
+            .sampler(*self.sampler)
+    }
+}
+
+pub fn create_sampler(
+    device: &Device,
+    filter: vk::Filter,
+    address_mode: vk::SamplerAddressMode,
+) -> Result<Owned<vk::Sampler>> {
+    let sampler_info = vk::SamplerCreateInfo::default()
+        .mag_filter(filter)
+        .min_filter(filter)
+        .address_mode_u(address_mode)
+        .address_mode_v(address_mode)
+        .address_mode_w(address_mode)
+        .anisotropy_enable(false)
+        .max_anisotropy(1.0)
+        .border_color(vk::BorderColor::INT_OPAQUE_BLACK)
+        .unnormalized_coordinates(false)
+        .compare_enable(false)
+        .compare_op(vk::CompareOp::ALWAYS)
+        .mipmap_mode(vk::SamplerMipmapMode::LINEAR)
+        .mip_lod_bias(0.0)
+        .min_lod(0.0)
+        .max_lod(0.0);
+
+    unsafe { Ok(Owned::new(device, device.create_sampler(&sampler_info, None)?)) }
+}
+
+// copies the pixels in through a staging buffer, leaving the image ready for sampling
+unsafe fn upload_pixels(
+    device: &Device,
+    allocator: &mut Allocator,
+    queue: vk::Queue,
+    command_pool: vk::CommandPool,
+    image: vk::Image,
+    extent: vk::Extent2D,
+    pixels: &[u8],
+) -> Result<()> {
+    let size = pixels.len() as vk::DeviceSize;
+
+    let staging = BufferData::create(allocator, size, vk::BufferUsageFlags::TRANSFER_SRC, MemoryUsage::CPU_ONLY)?;
+    staging.allocation.write(pixels)?;
+
+    super::single_time_commands(device, queue, command_pool, |command_buffer| {
+        transition_layout(
+            device,
+            command_buffer,
+            image,
+            (vk::ImageLayout::UNDEFINED, vk::ImageLayout::TRANSFER_DST_OPTIMAL),
+            (vk::AccessFlags::empty(), vk::AccessFlags::TRANSFER_WRITE),
+            (vk::PipelineStageFlags::TOP_OF_PIPE, vk::PipelineStageFlags::TRANSFER),
+        );
+
+        let subresource = vk::ImageSubresourceLayers::default()
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+            .mip_level(0)
+            .base_array_layer(0)
+            .layer_count(1);
+
+        // zero row length and height mean tightly packed
+        let region = vk::BufferImageCopy::default()
+            .buffer_offset(0)
+            .buffer_row_length(0)
+            .buffer_image_height(0)
+            .image_subresource(subresource)
+            .image_offset(vk::Offset3D::default())
+            .image_extent(extent.into());
+
+        device.cmd_copy_buffer_to_image(command_buffer, *staging.buffer, image, vk::ImageLayout::TRANSFER_DST_OPTIMAL, &[region]);
+
+        transition_layout(
+            device,
+            command_buffer,
+            image,
+            (vk::ImageLayout::TRANSFER_DST_OPTIMAL, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+            (vk::AccessFlags::TRANSFER_WRITE, vk::AccessFlags::SHADER_READ),
+            (vk::PipelineStageFlags::TRANSFER, vk::PipelineStageFlags::FRAGMENT_SHADER),
+        );
+    })
+}
+
+// (old, new) pairs for the layout, access masks and stages of a single colour image barrier
+unsafe fn transition_layout(
+    device: &Device,
+    command_buffer: vk::CommandBuffer,
+    image: vk::Image,
+    layouts: (vk::ImageLayout, vk::ImageLayout),
+    access: (vk::AccessFlags, vk::AccessFlags),
+    stages: (vk::PipelineStageFlags, vk::PipelineStageFlags),
+) {
+    let subresource_range = vk::ImageSubresourceRange::default()
+        .aspect_mask(vk::ImageAspectFlags::COLOR)
+        .base_mip_level(0)
+        .level_count(1)
+        .base_array_layer(0)
+        .layer_count(1);
+
+    let barrier = vk::ImageMemoryBarrier::default()
+        .old_layout(layouts.0)
+        .new_layout(layouts.1)
+        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+        .image(image)
+        .subresource_range(subresource_range)
+        .src_access_mask(access.0)
+        .dst_access_mask(access.1);
+
+    device.cmd_pipeline_barrier(
+        command_buffer,
+        stages.0,
+        stages.1,
+        vk::DependencyFlags::empty(),
+        &[] as &[vk::MemoryBarrier],
+        &[] as &[vk::BufferMemoryBarrier],
+        &[barrier],
+    );
+}
@@ -0,0 +1,423 @@
+// prints everything we know about each physical device, like a cut down vulkaninfo.
+// usage: device_report [--json]
+
+use std::ffi::CStr;
+
+use vulkan_testing::{
+    base::{create_instance, create_surface, data, device::format_uuid, owned::OwnedInstance},
+    util::string_from_utf8,
+};
+
+use ash::{vk, Entry, Instance};
+
+use winit::{event_loop::EventLoop, window::WindowBuilder};
+
+use anyhow::Result;
+
+use log::*;
+
+use serde_json::{json, Map, Value};
+
+fn main() -> Result<()> {
+    pretty_env_logger::init();
+
+    let json_output = std::env::args().skip(1).any(|a| a == "--json");
+
+    let entry = Entry::linked();
+
+    // a hidden window gets us a surface for the present support and surface format sections,
+    // but the rest of the report is still useful without a display
+    let event_loop = EventLoop::new();
+    let window = match &event_loop {
+        Ok(event_loop) => WindowBuilder::new()
+            .with_visible(false)
+            .build(event_loop)
+            .map_err(|e| warn!("Failed to create window, skipping surface info: {}", e))
+            .ok(),
+        Err(e) => {
+            warn!("No display available, skipping surface info: {}", e);
+            None
+        },
+    };
+
+    // dropped in reverse order, so the surface always goes before the instance, whichever way
+    // main returns
+    let instance = OwnedInstance::new(create_instance(window.as_ref(), &entry)?);
+
+    let surface_data = match &window {
+        Some(window) => Some(create_surface(&entry, &instance, window)?),
+        None => None,
+    };
+
+    let report = unsafe { build_report(&entry, &instance, surface_data.as_ref())? };
+
+    if json_output {
+        println!("{}", serde_json::to_string_pretty(&report)?);
+    } else {
+        let mut text = String::new();
+        write_text(&mut text, &report, 0);
+        print!("{}", text);
+    }
+
+    Ok(())
+}
+
+/*
+ * Report building
+ */
+
+unsafe fn build_report(
+    entry: &Entry,
+    instance: &Instance,
+    surface_data: Option<&data::SurfaceData>,
+) -> Result<Value> {
+    let instance_version = entry.try_enumerate_instance_version()?.unwrap_or(vk::API_VERSION_1_0);
+
+    let instance_extensions = entry.enumerate_instance_extension_properties(None)?
+        .iter()
+        .map(|e| json!(format!("{} (v{})", cstr(&e.extension_name), e.spec_version)))
+        .collect::<Vec<_>>();
+
+    let layers = entry.enumerate_instance_layer_properties()?
+        .iter()
+        .map(|l| json!(format!("{} ({})", cstr(&l.layer_name), cstr(&l.description))))
+        .collect::<Vec<_>>();
+
+    let devices = instance.enumerate_physical_devices()?
+        .into_iter()
+        .enumerate()
+        .map(|(i, d)| device_report(instance, surface_data, i, d))
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(json!({
+        "instance": {
+            "api_version": format_version(instance_version),
+            "extensions": instance_extensions,
+            "layers": layers,
+        },
+        "devices": devices,
+    }))
+}
+
+unsafe fn device_report(
+    instance: &Instance,
+    surface_data: Option<&data::SurfaceData>,
+    index: usize,
+    device: vk::PhysicalDevice,
+) -> Result<Value> {
+    let properties = instance.get_physical_device_properties(device);
+    let features = instance.get_physical_device_features(device);
+    let memory_properties = instance.get_physical_device_memory_properties(device);
+
+    let mut report = Map::new();
+    report.insert("index".into(), json!(index));
+    report.insert("name".into(), json!(string_from_utf8(&properties.device_name)));
+
+    let mut properties_report = json!({
+        "device_type": format!("{:?}", properties.device_type),
+        "api_version": format_version(properties.api_version),
+        "driver_version": properties.driver_version,
+        "vendor_id": format!("{:#06x}", properties.vendor_id),
+        "device_id": format!("{:#06x}", properties.device_id),
+        "pipeline_cache_uuid": format_uuid(&properties.pipeline_cache_uuid),
+    });
+
+    // the device and driver UUIDs need vulkan 1.1
+    if properties.api_version >= vk::API_VERSION_1_1 {
+        let mut id_properties = vk::PhysicalDeviceIDProperties::default();
+        let mut properties2 = vk::PhysicalDeviceProperties2::default()
+            .push_next(&mut id_properties);
+        instance.get_physical_device_properties2(device, &mut properties2);
+
+        properties_report["device_uuid"] = json!(format_uuid(&id_properties.device_uuid));
+        properties_report["driver_uuid"] = json!(format_uuid(&id_properties.driver_uuid));
+    }
+
+    report.insert("properties".into(), properties_report);
+    report.insert("limits".into(), limits_report(&properties.limits));
+    report.insert("features".into(), features_report(&features));
+
+    let extensions = instance.enumerate_device_extension_properties(device)?
+        .iter()
+        .map(|e| json!(format!("{} (v{})", cstr(&e.extension_name), e.spec_version)))
+        .collect::<Vec<_>>();
+    report.insert("extensions".into(), json!(extensions));
+
+    let heaps = memory_properties.memory_heaps[..memory_properties.memory_heap_count as usize]
+        .iter()
+        .enumerate()
+        .map(|(i, h)| json!({
+            "index": i,
+            "size_mib": h.size / (1024 * 1024),
+            "flags": format!("{:?}", h.flags),
+        }))
+        .collect::<Vec<_>>();
+
+    let types = memory_properties.memory_types[..memory_properties.memory_type_count as usize]
+        .iter()
+        .enumerate()
+        .map(|(i, t)| json!({
+            "index": i,
+            "heap_index": t.heap_index,
+            "flags": format!("{:?}", t.property_flags),
+        }))
+        .collect::<Vec<_>>();
+
+    report.insert("memory".into(), json!({ "heaps": heaps, "types": types }));
+
+    let queue_families = instance.get_physical_device_queue_family_properties(device)
+        .iter()
+        .enumerate()
+        .map(|(i, q)| {
+            let mut family = json!({
+                "index": i,
+                "flags": format!("{:?}", q.queue_flags),
+                "count": q.queue_count,
+                "timestamp_valid_bits": q.timestamp_valid_bits,
+                "min_image_transfer_granularity": [
+                    q.min_image_transfer_granularity.width,
+                    q.min_image_transfer_granularity.height,
+                    q.min_image_transfer_granularity.depth,
+                ],
+            });
+
+            if let Some(surface_data) = surface_data {
+                let present = surface_data.loader
+                    .get_physical_device_surface_support(device, i as u32, surface_data.surface)
+                    .unwrap_or(false);
+                family["present"] = json!(present);
+            }
+
+            family
+        })
+        .collect::<Vec<_>>();
+    report.insert("queue_families".into(), json!(queue_families));
+
+    // same lookup App uses, so a failure here explains a rejected device
+    let chosen_queues = match data::QueueFamilyIndices::get(instance, surface_data, device) {
+        Ok(indices) => json!({ "graphics": indices.graphics, "present": indices.present }),
+        Err(e) => json!(e.to_string()),
+    };
+    report.insert("chosen_queue_families".into(), chosen_queues);
+
+    if let Some(surface_data) = surface_data {
+        report.insert("surface".into(), surface_report(surface_data, device));
+    }
+
+    Ok(Value::Object(report))
+}
+
+unsafe fn surface_report(surface_data: &data::SurfaceData, device: vk::PhysicalDevice) -> Value {
+    let support = match data::SwapchainSupport::get(surface_data, device) {
+        Ok(support) => support,
+        Err(e) => return json!(e.to_string()),
+    };
+
+    let capabilities = &support.capabilities;
+
+    let formats = support.formats
+        .iter()
+        .map(|f| json!(format!("{:?} / {:?}", f.format, f.color_space)))
+        .collect::<Vec<_>>();
+
+    let present_modes = support.present_modes
+        .iter()
+        .map(|m| json!(format!("{:?}", m)))
+        .collect::<Vec<_>>();
+
+    json!({
+        "capabilities": {
+            "min_image_count": capabilities.min_image_count,
+            "max_image_count": capabilities.max_image_count,
+            "current_extent": [capabilities.current_extent.width, capabilities.current_extent.height],
+            "min_image_extent": [capabilities.min_image_extent.width, capabilities.min_image_extent.height],
+            "max_image_extent": [capabilities.max_image_extent.width, capabilities.max_image_extent.height],
+            "max_image_array_layers": capabilities.max_image_array_layers,
+            "supported_transforms": format!("{:?}", capabilities.supported_transforms),
+            "supported_composite_alpha": format!("{:?}", capabilities.supported_composite_alpha),
+            "supported_usage_flags": format!("{:?}", capabilities.supported_usage_flags),
+        },
+        "formats": formats,
+        "present_modes": present_modes,
+    })
+}
+
+// turns a field into json, flags are shown the same way as everywhere else in the report
+trait ReportValue {
+    fn report(&self) -> Value;
+}
+
+macro_rules! impl_report_value {
+    ($($t:ty),*) => {
+        $(impl ReportValue for $t {
+            fn report(&self) -> Value {
+                json!(self)
+            }
+        })*
+    };
+}
+
+impl_report_value!(u32, i32, u64, usize, f32);
+
+impl<T: ReportValue, const N: usize> ReportValue for [T; N] {
+    fn report(&self) -> Value {
+        Value::Array(self.iter().map(|v| v.report()).collect())
+    }
+}
+
+impl ReportValue for vk::SampleCountFlags {
+    fn report(&self) -> Value {
+        json!(format!("{:?}", self))
+    }
+}
+
+macro_rules! report_fields {
+    ($value:expr, [$($field:ident),* $(,)?]) => {{
+        let mut map = Map::new();
+        $(map.insert(stringify!($field).into(), $value.$field.report());)*
+        Value::Object(map)
+    }};
+}
+
+fn limits_report(limits: &vk::PhysicalDeviceLimits) -> Value {
+    report_fields!(limits, [
+        max_image_dimension1_d, max_image_dimension2_d, max_image_dimension3_d, max_image_dimension_cube,
+        max_image_array_layers, max_texel_buffer_elements, max_uniform_buffer_range, max_storage_buffer_range,
+        max_push_constants_size, max_memory_allocation_count, max_sampler_allocation_count,
+        buffer_image_granularity, sparse_address_space_size, max_bound_descriptor_sets,
+        max_per_stage_descriptor_samplers, max_per_stage_descriptor_uniform_buffers,
+        max_per_stage_descriptor_storage_buffers, max_per_stage_descriptor_sampled_images,
+        max_per_stage_descriptor_storage_images, max_per_stage_descriptor_input_attachments,
+        max_per_stage_resources, max_descriptor_set_samplers, max_descriptor_set_uniform_buffers,
+        max_descriptor_set_uniform_buffers_dynamic, max_descriptor_set_storage_buffers,
+        max_descriptor_set_storage_buffers_dynamic, max_descriptor_set_sampled_images,
+        max_descriptor_set_storage_images, max_descriptor_set_input_attachments,
+        max_vertex_input_attributes, max_vertex_input_bindings, max_vertex_input_attribute_offset,
+        max_vertex_input_binding_stride, max_vertex_output_components, max_tessellation_generation_level,
+        max_tessellation_patch_size, max_tessellation_control_per_vertex_input_components,
+        max_tessellation_control_per_vertex_output_components,
+        max_tessellation_control_per_patch_output_components,
+        max_tessellation_control_total_output_components, max_tessellation_evaluation_input_components,
+        max_tessellation_evaluation_output_components, max_geometry_shader_invocations,
+        max_geometry_input_components, max_geometry_output_components, max_geometry_output_vertices,
+        max_geometry_total_output_components, max_fragment_input_components,
+        max_fragment_output_attachments, max_fragment_dual_src_attachments,
+        max_fragment_combined_output_resources, max_compute_shared_memory_size,
+        max_compute_work_group_count, max_compute_work_group_invocations, max_compute_work_group_size,
+        sub_pixel_precision_bits, sub_texel_precision_bits, mipmap_precision_bits,
+        max_draw_indexed_index_value, max_draw_indirect_count, max_sampler_lod_bias,
+        max_sampler_anisotropy, max_viewports, max_viewport_dimensions, viewport_bounds_range,
+        viewport_sub_pixel_bits, min_memory_map_alignment, min_texel_buffer_offset_alignment,
+        min_uniform_buffer_offset_alignment, min_storage_buffer_offset_alignment, min_texel_offset,
+        max_texel_offset, min_texel_gather_offset, max_texel_gather_offset, min_interpolation_offset,
+        max_interpolation_offset, sub_pixel_interpolation_offset_bits, max_framebuffer_width,
+        max_framebuffer_height, max_framebuffer_layers, framebuffer_color_sample_counts,
+        framebuffer_depth_sample_counts, framebuffer_stencil_sample_counts,
+        framebuffer_no_attachments_sample_counts, max_color_attachments,
+        sampled_image_color_sample_counts, sampled_image_integer_sample_counts,
+        sampled_image_depth_sample_counts, sampled_image_stencil_sample_counts,
+        storage_image_sample_counts, max_sample_mask_words, timestamp_compute_and_graphics,
+        timestamp_period, max_clip_distances, max_cull_distances, max_combined_clip_and_cull_distances,
+        discrete_queue_priorities, point_size_range, line_width_range, point_size_granularity,
+        line_width_granularity, strict_lines, standard_sample_locations,
+        optimal_buffer_copy_offset_alignment, optimal_buffer_copy_row_pitch_alignment,
+        non_coherent_atom_size,
+    ])
+}
+
+fn features_report(features: &vk::PhysicalDeviceFeatures) -> Value {
+    let report = report_fields!(features, [
+        robust_buffer_access, full_draw_index_uint32, image_cube_array, independent_blend,
+        geometry_shader, tessellation_shader, sample_rate_shading, dual_src_blend, logic_op,
+        multi_draw_indirect, draw_indirect_first_instance, depth_clamp, depth_bias_clamp,
+        fill_mode_non_solid, depth_bounds, wide_lines, large_points, alpha_to_one, multi_viewport,
+        sampler_anisotropy, texture_compression_etc2, texture_compression_astc_ldr,
+        texture_compression_bc, occlusion_query_precise, pipeline_statistics_query,
+        vertex_pipeline_stores_and_atomics, fragment_stores_and_atomics,
+        shader_tessellation_and_geometry_point_size, shader_image_gather_extended,
+        shader_storage_image_extended_formats, shader_storage_image_multisample,
+        shader_storage_image_read_without_format, shader_storage_image_write_without_format,
+        shader_uniform_buffer_array_dynamic_indexing, shader_sampled_image_array_dynamic_indexing,
+        shader_storage_buffer_array_dynamic_indexing, shader_storage_image_array_dynamic_indexing,
+        shader_clip_distance, shader_cull_distance, shader_float64, shader_int64, shader_int16,
+        shader_resource_residency, shader_resource_min_lod, sparse_binding, sparse_residency_buffer,
+        sparse_residency_image2_d, sparse_residency_image3_d, sparse_residency2_samples,
+        sparse_residency4_samples, sparse_residency8_samples, sparse_residency16_samples,
+        sparse_residency_aliased, variable_multisample_rate, inherited_queries,
+    ]);
+
+    // features are all Bool32, show them as actual booleans
+    match report {
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .map(|(k, v)| (k, json!(v.as_u64() == Some(vk::TRUE as u64))))
+                .collect()
+        ),
+        other => other,
+    }
+}
+
+/*
+ * Formatting
+ */
+
+// renders the json report as an indented tree
+fn write_text(out: &mut String, value: &Value, depth: usize) {
+    let indent = "  ".repeat(depth);
+
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                match value {
+                    Value::Object(_) | Value::Array(_) if !is_inline(value) => {
+                        out.push_str(&format!("{}{}:\n", indent, key));
+                        write_text(out, value, depth + 1);
+                    },
+                    _ => out.push_str(&format!("{}{}: {}\n", indent, key, inline(value))),
+                }
+            }
+        },
+        Value::Array(values) => {
+            for (i, value) in values.iter().enumerate() {
+                match value {
+                    Value::Object(_) | Value::Array(_) if !is_inline(value) => {
+                        out.push_str(&format!("{}[{}]\n", indent, i));
+                        write_text(out, value, depth + 1);
+                    },
+                    _ => out.push_str(&format!("{}{}\n", indent, inline(value))),
+                }
+            }
+        },
+        _ => out.push_str(&format!("{}{}\n", indent, inline(value))),
+    }
+}
+
+// short arrays of plain values, like extents, read better on one line
+fn is_inline(value: &Value) -> bool {
+    match value {
+        Value::Array(values) => values.len() <= 3 && values.iter().all(|v| v.is_number()),
+        Value::Object(_) => false,
+        _ => true,
+    }
+}
+
+fn inline(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Array(values) => format!("[{}]", values.iter().map(inline).collect::<Vec<_>>().join(", ")),
+        other => other.to_string(),
+    }
+}
+
+fn format_version(version: u32) -> String {
+    format!(
+        "{}.{}.{}",
+        vk::api_version_major(version),
+        vk::api_version_minor(version),
+        vk::api_version_patch(version),
+    )
+}
+
+fn cstr(chars: &[std::os::raw::c_char]) -> String {
+    unsafe { CStr::from_ptr(chars.as_ptr()) }.to_string_lossy().into_owned()
+}
@@ -1,4 +1,7 @@
-use vulkan_testing::{ base::App, util::constants::* };
+use vulkan_testing::{
+    base::{config::Config, data::UniformBufferObject, device::DeviceSelector, App},
+    util::constants::*,
+};
 
 use winit::{
     dpi::LogicalSize,
@@ -9,49 +12,114 @@ use winit::{
     window::WindowBuilder,
 };
 
-use anyhow::Result;
+use ash::vk;
+
+use glam::{Mat4, Vec3};
+
+use anyhow::{anyhow, Result};
 
 use log::*;
-use std::process;
+use std::{
+    process,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
 
 fn main() -> Result<()> {
     pretty_env_logger::init();
 
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+
+    let config = Config {
+        device: arg_value(&args, "--device")?
+            .map(DeviceSelector::parse)
+            .transpose()?,
+        msaa_samples: match arg_value(&args, "--msaa")? {
+            Some(samples) => samples.parse()?,
+            None => 1,
+        },
+        frames_in_flight: match arg_value(&args, "--frames-in-flight")? {
+            Some(frames) => frames.parse()?,
+            None => DEFAULT_FRAMES_IN_FLIGHT,
+        },
+        ..Default::default()
+    };
+
+    // optional png or jpeg to draw the triangle with
+    let texture = arg_value(&args, "--texture")?.map(str::to_string);
+
+    if args.iter().any(|a| a == "--headless") {
+        // number of frames to render before exiting
+        let frames = match arg_value(&args, "--frames")? {
+            Some(frames) => frames.parse()?,
+            None => 1,
+        };
+
+        // optionally save the last frame as a png
+        let output = arg_value(&args, "--output")?.map(str::to_string);
+
+        return run_headless(frames, output, texture, config);
+    }
+
     let mut event_loop = EventLoop::new()?;
 
     // create window with set size as per vulkan tutorial
     let window = WindowBuilder::new()
         .with_title(WINDOW_TITLE)
         .with_inner_size(LogicalSize::new(WINDOW_WIDTH, WINDOW_HEIGHT))
-        .with_resizable(false)
         .build(&event_loop)
         .unwrap();
 
-    let mut app = match App::create(window) {
+    let mut app = match App::create(window, config) {
         Ok(a) => a,
         Err(e) => {
-            error!("Error creating app: {:?}", e);
+            error!("Error creating app: {}", e);
             process::exit(1)
         }
     };
 
+    if let Some(path) = texture {
+        use_texture(&mut app, path)?;
+    }
+
+    let start = Instant::now();
+
     event_loop.run_on_demand(|event, elwt| {
         elwt.set_control_flow(ControlFlow::Poll);
         match event {
-            Event::AboutToWait => app.window.request_redraw(),
+            Event::AboutToWait => if let Some(window) = &app.window {
+                window.request_redraw()
+            },
             Event::WindowEvent { event, .. } => {
                 match event {
-                    WindowEvent::RedrawRequested if !elwt.exiting() => unsafe { app.render_frame() }.unwrap(),
+                    WindowEvent::RedrawRequested if !elwt.exiting() => {
+                        app.uniforms = spinning_uniforms(start.elapsed().as_secs_f32(), app.target.extent());
+                        if let Err(e) = unsafe { app.render_frame() } {
+                            error!("Failed to render frame: {}", e);
+                            elwt.exit();
+                        }
+                    },
+                    WindowEvent::Resized(_) => app.resized = true,
+                    WindowEvent::KeyboardInput { event: KeyEvent {
+                            logical_key: Key::Named(NamedKey::F12),
+                            state: ElementState::Pressed,
+                            repeat: false,
+                            ..
+                        }, ..
+                    } => save_screenshot(&mut app),
+                    WindowEvent::KeyboardInput { event: KeyEvent {
+                            logical_key: Key::Character(c),
+                            state: ElementState::Pressed,
+                            repeat: false,
+                            ..
+                        }, ..
+                    } if c.eq_ignore_ascii_case("m") => cycle_msaa(&mut app),
                     WindowEvent::KeyboardInput { event: KeyEvent {
                             logical_key: Key::Named(NamedKey::Escape),
                             state: ElementState::Pressed,
                             ..
                         }, ..
                     }
-                    | WindowEvent::CloseRequested => {
-                        elwt.exit();
-                        unsafe { app.destroy() };
-                    },
+                    | WindowEvent::CloseRequested => elwt.exit(),
                     _ => {},
                 }
             },
@@ -61,3 +129,99 @@ fn main() -> Result<()> {
 
     Ok(())
 }
+
+// value following a flag, e.g. `--frames 10`
+fn arg_value<'a>(args: &'a [String], flag: &str) -> Result<Option<&'a str>> {
+    match args.iter().position(|a| a == flag) {
+        Some(i) => args.get(i + 1)
+            .map(|v| Some(v.as_str()))
+            .ok_or_else(|| anyhow!("Missing value for {}.", flag)),
+        None => Ok(None),
+    }
+}
+
+// rotates the triangle about the z axis, seen from slightly in front of it
+fn spinning_uniforms(seconds: f32, extent: vk::Extent2D) -> UniformBufferObject {
+    let aspect = extent.width as f32 / extent.height.max(1) as f32;
+
+    // no y flip, so world -y stays at the top of the screen like the untransformed triangle
+    UniformBufferObject {
+        model: Mat4::from_rotation_z(seconds * std::f32::consts::FRAC_PI_2),
+        view: Mat4::look_at_rh(Vec3::new(0.0, 0.0, 2.0), Vec3::ZERO, Vec3::Y),
+        proj: Mat4::perspective_rh(std::f32::consts::FRAC_PI_4, aspect, 0.1, 10.0),
+    }
+}
+
+// sets the texture, and sets it again whenever the device is recreated as it goes with the old one
+fn use_texture(app: &mut App, path: String) -> Result<()> {
+    set_texture(app, &path)?;
+    app.set_device_reset_hook(move |app| set_texture(app, &path));
+
+    Ok(())
+}
+
+fn set_texture(app: &mut App, path: &str) -> Result<()> {
+    unsafe {
+        let texture = app.load_texture(path)?;
+        app.set_texture(texture)?;
+    }
+
+    info!("Loaded texture {}.", path);
+
+    Ok(())
+}
+
+// steps through 1x, 2x, 4x and 8x msaa, wrapping once the device maximum is reached
+fn cycle_msaa(app: &mut App) {
+    let next = match app.msaa_samples.as_raw() {
+        8.. => 1,
+        n => n * 2,
+    };
+
+    let result = unsafe { app.set_msaa_samples(next) }
+        .and_then(|samples| match samples.as_raw() < next {
+            // clamped to the device maximum, so wrap around instead
+            true => unsafe { app.set_msaa_samples(1) },
+            false => Ok(samples),
+        });
+
+    match result {
+        Ok(samples) => info!("Using {:?} msaa.", samples),
+        Err(e) => error!("Failed to change msaa: {:?}", e),
+    }
+}
+
+fn save_screenshot(app: &mut App) {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default();
+    let path = format!("screenshot-{}.png", timestamp);
+
+    match unsafe { app.capture_frame() }.map_err(anyhow::Error::from).and_then(|c| c.save_png(&path)) {
+        Ok(()) => info!("Saved screenshot to {}.", path),
+        Err(e) => error!("Failed to save screenshot: {:?}", e),
+    }
+}
+
+// renders without a window or surface, for machines without a display
+fn run_headless(frames: usize, output: Option<String>, texture: Option<String>, config: Config) -> Result<()> {
+    let mut app = App::create_headless(WINDOW_WIDTH, WINDOW_HEIGHT, config)?;
+
+    if let Some(path) = texture {
+        use_texture(&mut app, path)?;
+    }
+
+    info!("Rendering {} headless frame(s).", frames);
+    for i in 0..frames {
+        match &output {
+            Some(path) if i + 1 == frames => {
+                unsafe { app.capture_frame()? }.save_png(path)?;
+                info!("Saved last frame to {}.", path);
+            },
+            _ => unsafe { app.render_frame()? },
+        }
+    }
+
+    Ok(())
+}
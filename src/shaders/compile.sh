@@ -1,4 +0,0 @@
-#!/usr/bin/bash
-
-$(which glslc) shader.vert -o vert.spv
-$(which glslc) shader.frag -o frag.spv
@@ -1,11 +1,33 @@
 use std::ffi::CStr;
 
-pub const WINDOW_TITLE: &'static str = "Vulkan Testing";
+use crate::base::buffer::TexturedVertex;
+
+pub const WINDOW_TITLE: &str = "Vulkan Testing";
 pub const WINDOW_HEIGHT: u32 = 600;
 pub const WINDOW_WIDTH: u32 = 800;
 
 pub const VALIDATION_ENABLED: bool = cfg!(debug_assertions);
 
-pub const SHADER_MAIN: &CStr =  unsafe { &CStr::from_bytes_with_nul_unchecked(b"main\0") };
+pub const SHADER_MAIN: &CStr = c"main";
+
+// how many frames the cpu may record ahead of the gpu, see Config::frames_in_flight
+pub const DEFAULT_FRAMES_IN_FLIGHT: usize = 2;
+pub const MAX_FRAMES_IN_FLIGHT: usize = 4;
+
+// environment variable that forces a physical device, see base::device::DeviceSelector
+pub const DEVICE_ENV_VAR: &str = "VULKAN_DEVICE";
+
+// descriptor bindings of the scene shaders' set 0. the texture used to be one combined image
+// sampler at binding 1, but naga can't emit those, so the image and sampler are bound separately
+pub const UNIFORM_BINDING: u32 = 0;
+pub const TEXTURE_BINDING: u32 = 1;
+pub const SAMPLER_BINDING: u32 = 2;
+
+// the default scene
+pub const TRIANGLE_VERTICES: [TexturedVertex; 3] = [
+    TexturedVertex { pos: [0.0, -0.5], color: [1.0, 0.0, 0.0], tex_coord: [0.5, 0.0] },
+    TexturedVertex { pos: [0.5, 0.5], color: [0.0, 1.0, 0.0], tex_coord: [1.0, 1.0] },
+    TexturedVertex { pos: [-0.5, 0.5], color: [0.0, 0.0, 1.0], tex_coord: [0.0, 1.0] },
+];
 
-pub const MAX_FRAMES_IN_FLIGHT: usize = 2;
+pub const TRIANGLE_INDICES: [u16; 3] = [0, 1, 2];
@@ -1,10 +1,17 @@
 pub mod constants;
+pub mod spirv;
 
-use core::slice;
+use std::borrow::Cow;
 
 use anyhow::{Result, anyhow};
-use std::alloc::{alloc, dealloc, Layout};
 
+use spirv::ShaderReflection;
+
+/// Turns a nul padded name string filled in by vulkan into a `String`.
+///
+/// # Safety
+/// `string` must be valid utf-8 once the nuls are dropped, which vulkan guarantees for the names it
+/// reports.
 pub unsafe fn string_from_utf8(string: &[i8; 256]) -> String {
     std::str::from_utf8_unchecked(&string.iter()
                                   .filter(|&i| *i as u8 != b'\0')
@@ -30,39 +37,46 @@ pub unsafe fn string_from_utf8(string: &[i8; 256]) -> String {
 
 // the below Bytecode struct is modified by me, sourced from https://github.com/KyleMayes/vulkanalia/blob/master/vulkanalia/src/bytecode.rs.
 
-#[derive(Debug)]
-pub struct Bytecode(*mut u8, usize);
+// SPIR-V words, either embedded in the binary or loaded at runtime
+#[derive(Debug, Clone)]
+pub struct Bytecode(Cow<'static, [u32]>);
 
 impl Bytecode {
     pub fn from(bytecode: &[u8]) -> Result<Self> {
-        if bytecode.is_empty() || bytecode.len() % 4 != 0 {
+        if bytecode.is_empty() || !bytecode.len().is_multiple_of(4) {
             return Err(anyhow!("Invalid bytecode buffer length ({})", bytecode.len()));
         }
 
-        let layout = Layout::from_size_align(bytecode.len(), 4)?;
-        debug_assert_ne!(layout.size(), 0);
+        // copying into u32s gives the alignment vulkan needs, whatever the source alignment
+        let mut words = bytecode
+            .chunks_exact(4)
+            .map(|w| u32::from_ne_bytes([w[0], w[1], w[2], w[3]]))
+            .collect::<Vec<_>>();
 
-        let ptr = unsafe { alloc(layout) };
-        if ptr.is_null() {
-            return Err(anyhow!("Failed to allocate bytecode buffer."))
+        // modules written on a machine of the other endianness
+        if words[0].swap_bytes() == spirv::MAGIC {
+            words.iter_mut().for_each(|w| *w = w.swap_bytes());
         }
-        
-        let slice = unsafe { slice::from_raw_parts_mut(ptr, layout.size()) };
-        slice.copy_from_slice(bytecode);
 
-        Ok(Self(ptr, layout.size()))
+        spirv::check_header(&words)?;
+
+        Ok(Self(Cow::Owned(words)))
+    }
+
+    pub const fn from_static(words: &'static [u32]) -> Self {
+        Self(Cow::Borrowed(words))
+    }
+
+    pub fn from_words(words: Vec<u32>) -> Self {
+        Self(Cow::Owned(words))
     }
 
     pub fn code(&self) -> &[u32] {
-        let ptr: *const u32 = self.0.cast();
-        let len = self.1 / 4;
-        unsafe { slice::from_raw_parts(ptr, len) }
+        &self.0
     }
-}
 
-impl Drop for Bytecode {
-    fn drop(&mut self) {
-        let layout = Layout::from_size_align(self.1, 4).unwrap();
-        unsafe { dealloc(self.0, layout) };
+    // entry points, stage interfaces, descriptors and push constants of the module
+    pub fn reflect(&self) -> Result<ShaderReflection> {
+        spirv::reflect(&self.0)
     }
 }
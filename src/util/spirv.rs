@@ -0,0 +1,950 @@
+// just enough of a SPIR-V parser to check a module's header and reflect the interface
+// a pipeline has to match: entry points, stage inputs and outputs, descriptors and push constants
+
+use std::{borrow::Cow, collections::HashMap, fmt};
+
+use ash::vk;
+
+use anyhow::{anyhow, Result};
+
+pub const MAGIC: u32 = 0x0723_0203;
+const HEADER_WORDS: usize = 5;
+// newest version vulkan 1.3 accepts
+const MAX_MINOR_VERSION: u32 = 6;
+// deepest nesting of types followed before giving up, real shaders stay in single digits but a
+// malformed module can make a type contain itself
+const MAX_TYPE_DEPTH: u32 = 64;
+
+mod op {
+    pub const NAME: u32 = 5;
+    pub const ENTRY_POINT: u32 = 15;
+    pub const TYPE_BOOL: u32 = 20;
+    pub const TYPE_INT: u32 = 21;
+    pub const TYPE_FLOAT: u32 = 22;
+    pub const TYPE_VECTOR: u32 = 23;
+    pub const TYPE_MATRIX: u32 = 24;
+    pub const TYPE_IMAGE: u32 = 25;
+    pub const TYPE_SAMPLER: u32 = 26;
+    pub const TYPE_SAMPLED_IMAGE: u32 = 27;
+    pub const TYPE_ARRAY: u32 = 28;
+    pub const TYPE_RUNTIME_ARRAY: u32 = 29;
+    pub const TYPE_STRUCT: u32 = 30;
+    pub const TYPE_POINTER: u32 = 32;
+    pub const CONSTANT: u32 = 43;
+    pub const VARIABLE: u32 = 59;
+    pub const DECORATE: u32 = 71;
+    pub const MEMBER_DECORATE: u32 = 72;
+    pub const TYPE_ACCELERATION_STRUCTURE: u32 = 5341;
+}
+
+mod decoration {
+    pub const BLOCK: u32 = 2;
+    pub const BUFFER_BLOCK: u32 = 3;
+    pub const ARRAY_STRIDE: u32 = 6;
+    pub const MATRIX_STRIDE: u32 = 7;
+    pub const LOCATION: u32 = 30;
+    pub const BINDING: u32 = 33;
+    pub const DESCRIPTOR_SET: u32 = 34;
+    pub const OFFSET: u32 = 35;
+}
+
+mod storage {
+    pub const UNIFORM_CONSTANT: u32 = 0;
+    pub const INPUT: u32 = 1;
+    pub const UNIFORM: u32 = 2;
+    pub const OUTPUT: u32 = 3;
+    pub const PUSH_CONSTANT: u32 = 9;
+    pub const STORAGE_BUFFER: u32 = 12;
+}
+
+const DIM_BUFFER: u32 = 5;
+const DIM_SUBPASS_DATA: u32 = 6;
+
+// checks the header of native-endian words, returning the version as (major, minor)
+pub fn check_header(words: &[u32]) -> Result<(u32, u32)> {
+    if words.len() < HEADER_WORDS {
+        return Err(anyhow!("SPIR-V module is only {} words, too short for a header", words.len()));
+    }
+
+    if words[0] != MAGIC {
+        return Err(match words[0].swap_bytes() == MAGIC {
+            true => anyhow!("SPIR-V module is byte-swapped"),
+            false => anyhow!("Not a SPIR-V module, magic number is {:#010x}", words[0]),
+        });
+    }
+
+    let major = (words[1] >> 16) & 0xff;
+    let minor = (words[1] >> 8) & 0xff;
+    if major != 1 || minor > MAX_MINOR_VERSION {
+        return Err(anyhow!("Unsupported SPIR-V version {}.{}", major, minor));
+    }
+
+    if words[3] == 0 {
+        return Err(anyhow!("SPIR-V module has an id bound of 0"));
+    }
+
+    Ok((major, minor))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScalarType {
+    Bool,
+    Int,
+    Uint,
+    Float,
+}
+
+// type of a stage input or output, in glsl terms
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InterfaceType {
+    pub scalar: ScalarType,
+    // in bits
+    pub width: u32,
+    // rows of a matrix, 1 for scalars
+    pub components: u32,
+    // 1 unless it's a matrix
+    pub columns: u32,
+    // 1 unless it's an array
+    pub array_length: u32,
+}
+
+impl InterfaceType {
+    // number of consecutive locations the variable occupies
+    pub fn locations(&self) -> u32 {
+        // 64 bit vectors of 3 or 4 components take two locations
+        let per_column = match self.width == 64 && self.components > 2 {
+            true => 2u32,
+            false => 1,
+        };
+
+        // saturating, as a malformed module can declare any array length
+        per_column.saturating_mul(self.columns).saturating_mul(self.array_length)
+    }
+}
+
+impl fmt::Display for InterfaceType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let prefix = match (self.scalar, self.width) {
+            (ScalarType::Float, 64) => "d",
+            (ScalarType::Float, _) => "",
+            (ScalarType::Int, _) => "i",
+            (ScalarType::Uint, _) => "u",
+            (ScalarType::Bool, _) => "b",
+        };
+
+        match (self.components, self.columns) {
+            (1, _) => match (self.scalar, self.width) {
+                (ScalarType::Float, 64) => write!(f, "double")?,
+                (ScalarType::Float, _) => write!(f, "float")?,
+                (ScalarType::Int, _) => write!(f, "int")?,
+                (ScalarType::Uint, _) => write!(f, "uint")?,
+                (ScalarType::Bool, _) => write!(f, "bool")?,
+            },
+            (rows, 1) => write!(f, "{}vec{}", prefix, rows)?,
+            (rows, columns) if rows == columns => write!(f, "{}mat{}", prefix, columns)?,
+            (rows, columns) => write!(f, "{}mat{}x{}", prefix, columns, rows)?,
+        }
+
+        if self.array_length > 1 {
+            write!(f, "[{}]", self.array_length)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InterfaceVariable {
+    pub location: u32,
+    pub name: Option<String>,
+    pub ty: InterfaceType,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryPoint {
+    pub name: String,
+    pub stage: vk::ShaderStageFlags,
+    // builtins are left out, only variables with a location are listed
+    pub inputs: Vec<InterfaceVariable>,
+    pub outputs: Vec<InterfaceVariable>,
+}
+
+impl InterfaceVariable {
+    // e.g. "vec2 fragTexCoord", or just the type when names were stripped
+    fn describe(&self) -> String {
+        match &self.name {
+            Some(name) => format!("{} {}", self.ty, name),
+            None => self.ty.to_string(),
+        }
+    }
+}
+
+impl EntryPoint {
+    // checks every input location is fed by an attribute of the same numeric type and size
+    pub fn check_vertex_input(&self, attributes: &[vk::VertexInputAttributeDescription]) -> Result<()> {
+        for input in &self.inputs {
+            // matrices and arrays take one attribute per column or element
+            for location in input.location..input.location.saturating_add(input.ty.locations()) {
+                let attribute = attributes
+                    .iter()
+                    .find(|a| a.location == location)
+                    .ok_or_else(|| anyhow!(
+                        "{:?} shader input at location {} ({}) has no vertex attribute",
+                        self.stage, location, input.describe(),
+                    ))?;
+
+                let (scalar, width, components) = vertex_format_type(attribute.format).ok_or_else(|| anyhow!(
+                    "Vertex attribute at location {} has format {:?}, which can't be checked against the {:?} shader",
+                    location, attribute.format, self.stage,
+                ))?;
+
+                if scalar != input.ty.scalar || width != input.ty.width || components != input.ty.components {
+                    return Err(anyhow!(
+                        "Vertex attribute at location {} is {:?}, but the {:?} shader reads a {}",
+                        location, attribute.format, self.stage, input.describe(),
+                    ));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // checks every input is written by the previous stage with a matching type. the output may
+    // have more vector components than are read
+    pub fn check_inputs_from(&self, previous: &EntryPoint) -> Result<()> {
+        for input in &self.inputs {
+            let output = previous.outputs
+                .iter()
+                .find(|o| o.location == input.location)
+                .ok_or_else(|| anyhow!(
+                    "{:?} shader reads location {} ({}), which the {:?} shader doesn't write",
+                    self.stage, input.location, input.describe(), previous.stage,
+                ))?;
+
+            let compatible = output.ty.scalar == input.ty.scalar
+                && output.ty.width == input.ty.width
+                && output.ty.columns == input.ty.columns
+                && output.ty.array_length == input.ty.array_length
+                && output.ty.components >= input.ty.components;
+
+            if !compatible {
+                return Err(anyhow!(
+                    "{:?} shader writes a {} to location {}, but the {:?} shader reads it as a {}",
+                    previous.stage, output.describe(), input.location, self.stage, input.describe(),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DescriptorBinding {
+    pub set: u32,
+    pub binding: u32,
+    pub descriptor_type: vk::DescriptorType,
+    // 0 for runtime sized arrays
+    pub count: u32,
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShaderReflection {
+    pub version: (u32, u32),
+    pub entry_points: Vec<EntryPoint>,
+    // every binding declared in the module, whichever entry point uses it
+    pub descriptor_bindings: Vec<DescriptorBinding>,
+    // size in bytes of the push constant block, if there is one
+    pub push_constant_size: Option<u32>,
+}
+
+impl ShaderReflection {
+    pub fn entry_point(&self, name: &str, stage: vk::ShaderStageFlags) -> Result<&EntryPoint> {
+        self.entry_points
+            .iter()
+            .find(|e| e.name == name && e.stage == stage)
+            .ok_or_else(|| anyhow!(
+                "Shader has no {:?} entry point named \"{}\", only {}",
+                stage,
+                name,
+                self.entry_points
+                    .iter()
+                    .map(|e| format!("\"{}\" ({:?})", e.name, e.stage))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+            ))
+    }
+
+    // checks every descriptor and push constant the shader declares is in the layout, visible to
+    // stage. set_bindings holds the bindings of each descriptor set layout, indexed by set number
+    pub fn check_layout(
+        &self,
+        stage: vk::ShaderStageFlags,
+        set_bindings: &[&[vk::DescriptorSetLayoutBinding]],
+        push_constant_ranges: &[vk::PushConstantRange],
+    ) -> Result<()> {
+        for binding in &self.descriptor_bindings {
+            let name = binding.name.as_deref().unwrap_or("<unnamed>");
+
+            let layout_binding = set_bindings
+                .get(binding.set as usize)
+                .and_then(|b| b.iter().find(|b| b.binding == binding.binding))
+                .ok_or_else(|| anyhow!(
+                    "{:?} shader uses {} at set {} binding {}, which is not in the pipeline layout",
+                    stage, name, binding.set, binding.binding,
+                ))?;
+
+            if layout_binding.descriptor_type != binding.descriptor_type {
+                return Err(anyhow!(
+                    "{:?} shader declares {} at set {} binding {} as {:?}, but the layout has {:?}",
+                    stage, name, binding.set, binding.binding, binding.descriptor_type, layout_binding.descriptor_type,
+                ));
+            }
+
+            if layout_binding.descriptor_count < binding.count {
+                return Err(anyhow!(
+                    "{:?} shader declares {} descriptors for {} at set {} binding {}, but the layout only has {}",
+                    stage, binding.count, name, binding.set, binding.binding, layout_binding.descriptor_count,
+                ));
+            }
+
+            if !layout_binding.stage_flags.contains(stage) {
+                return Err(anyhow!(
+                    "{} at set {} binding {} is used by the {:?} shader, but only visible to {:?}",
+                    name, binding.set, binding.binding, stage, layout_binding.stage_flags,
+                ));
+            }
+        }
+
+        if let Some(size) = self.push_constant_size {
+            let covered = push_constant_ranges
+                .iter()
+                .filter(|r| r.stage_flags.contains(stage) && r.offset == 0)
+                .map(|r| r.size)
+                .max()
+                .unwrap_or(0);
+
+            if covered < size {
+                return Err(anyhow!(
+                    "{:?} shader has a {} byte push constant block, but the layout only gives it {} bytes",
+                    stage, size, covered,
+                ));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// what a shader sees when reading a vertex attribute of a format, None for formats that are
+// rarely used for vertices
+fn vertex_format_type(format: vk::Format) -> Option<(ScalarType, u32, u32)> {
+    use vk::Format as F;
+
+    Some(match format {
+        F::R32_SFLOAT | F::R16_SFLOAT | F::R16_UNORM | F::R16_SNORM | F::R8_UNORM | F::R8_SNORM => (ScalarType::Float, 32, 1),
+        F::R32G32_SFLOAT | F::R16G16_SFLOAT | F::R16G16_UNORM | F::R16G16_SNORM | F::R8G8_UNORM | F::R8G8_SNORM => (ScalarType::Float, 32, 2),
+        F::R32G32B32_SFLOAT | F::R16G16B16_SFLOAT | F::R16G16B16_UNORM | F::R16G16B16_SNORM | F::R8G8B8_UNORM | F::R8G8B8_SNORM => {
+            (ScalarType::Float, 32, 3)
+        },
+        F::R32G32B32A32_SFLOAT | F::R16G16B16A16_SFLOAT | F::R16G16B16A16_UNORM | F::R16G16B16A16_SNORM
+        | F::R8G8B8A8_UNORM | F::R8G8B8A8_SNORM | F::B8G8R8A8_UNORM | F::A2B10G10R10_UNORM_PACK32 => (ScalarType::Float, 32, 4),
+        F::R32_SINT | F::R16_SINT | F::R8_SINT => (ScalarType::Int, 32, 1),
+        F::R32G32_SINT | F::R16G16_SINT | F::R8G8_SINT => (ScalarType::Int, 32, 2),
+        F::R32G32B32_SINT | F::R16G16B16_SINT | F::R8G8B8_SINT => (ScalarType::Int, 32, 3),
+        F::R32G32B32A32_SINT | F::R16G16B16A16_SINT | F::R8G8B8A8_SINT => (ScalarType::Int, 32, 4),
+        F::R32_UINT | F::R16_UINT | F::R8_UINT => (ScalarType::Uint, 32, 1),
+        F::R32G32_UINT | F::R16G16_UINT | F::R8G8_UINT => (ScalarType::Uint, 32, 2),
+        F::R32G32B32_UINT | F::R16G16B16_UINT | F::R8G8B8_UINT => (ScalarType::Uint, 32, 3),
+        F::R32G32B32A32_UINT | F::R16G16B16A16_UINT | F::R8G8B8A8_UINT => (ScalarType::Uint, 32, 4),
+        F::R64_SFLOAT => (ScalarType::Float, 64, 1),
+        F::R64G64_SFLOAT => (ScalarType::Float, 64, 2),
+        F::R64G64B64_SFLOAT => (ScalarType::Float, 64, 3),
+        F::R64G64B64A64_SFLOAT => (ScalarType::Float, 64, 4),
+        _ => return None,
+    })
+}
+
+#[derive(Debug, Clone)]
+enum Type {
+    Bool,
+    Int { width: u32, signed: bool },
+    Float { width: u32 },
+    Vector { component: u32, count: u32 },
+    Matrix { column: u32, count: u32 },
+    Image { dim: u32, sampled: u32 },
+    Sampler,
+    SampledImage,
+    Array { element: u32, length: u32 },
+    RuntimeArray { element: u32 },
+    Struct { members: Vec<u32> },
+    Pointer { pointee: u32 },
+    AccelerationStructure,
+}
+
+struct Variable {
+    id: u32,
+    ty: u32,
+    storage: u32,
+}
+
+struct RawEntryPoint {
+    name: String,
+    execution_model: u32,
+    interface: Vec<u32>,
+}
+
+#[derive(Default)]
+struct Module {
+    names: HashMap<u32, String>,
+    entry_points: Vec<RawEntryPoint>,
+    types: HashMap<u32, Type>,
+    constants: HashMap<u32, u32>,
+    variables: Vec<Variable>,
+    // (id, decoration) -> first literal, 0 for decorations without one
+    decorations: HashMap<(u32, u32), u32>,
+    // (struct id, member, decoration) -> first literal
+    member_decorations: HashMap<(u32, u32, u32), u32>,
+}
+
+pub fn reflect(words: &[u32]) -> Result<ShaderReflection> {
+    // reflect whatever order the words come in, vulkan itself accepts either
+    let words = match words.first().map(|w| w.swap_bytes()) {
+        Some(MAGIC) => Cow::Owned(words.iter().map(|w| w.swap_bytes()).collect()),
+        _ => Cow::Borrowed(words),
+    };
+
+    let version = check_header(&words)?;
+    let module = parse(&words)?;
+
+    let entry_points = module.entry_points
+        .iter()
+        .map(|e| module.entry_point(e))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut descriptor_bindings = vec![];
+    let mut push_constant_size = None;
+
+    for variable in &module.variables {
+        match variable.storage {
+            storage::UNIFORM_CONSTANT | storage::UNIFORM | storage::STORAGE_BUFFER => {
+                descriptor_bindings.push(module.descriptor_binding(variable)?);
+            },
+            storage::PUSH_CONSTANT => {
+                push_constant_size = Some(module.size_of(module.pointee(variable.ty)?, 0)?);
+            },
+            _ => {},
+        }
+    }
+
+    descriptor_bindings.sort_by_key(|b| (b.set, b.binding));
+
+    Ok(
+        ShaderReflection {
+            version,
+            entry_points,
+            descriptor_bindings,
+            push_constant_size,
+        }
+    )
+}
+
+fn parse(words: &[u32]) -> Result<Module> {
+    let bound = words[3];
+    let mut module = Module::default();
+
+    let mut i = HEADER_WORDS;
+    while i < words.len() {
+        let word_count = (words[i] >> 16) as usize;
+        let opcode = words[i] & 0xffff;
+
+        if word_count == 0 || i + word_count > words.len() {
+            return Err(anyhow!("SPIR-V instruction at word {} has an invalid length of {}", i, word_count));
+        }
+
+        let operands = &words[i + 1..i + word_count];
+        let operand = |n: usize| operands
+            .get(n)
+            .copied()
+            .ok_or_else(|| anyhow!("SPIR-V instruction {} at word {} is missing operands", opcode, i));
+        // operands from n on, for strings and lists that run to the end of the instruction
+        let operands_from = |n: usize| operands
+            .get(n..)
+            .ok_or_else(|| anyhow!("SPIR-V instruction {} at word {} is missing operands", opcode, i));
+        // result ids are always the first operand of the instructions read here
+        let result_id = || operand(0).and_then(|id| match id < bound {
+            true => Ok(id),
+            false => Err(anyhow!("SPIR-V id {} at word {} is outside the bound of {}", id, i, bound)),
+        });
+
+        match opcode {
+            op::NAME => {
+                module.names.insert(operand(0)?, parse_string(operands_from(1)?));
+            },
+            op::ENTRY_POINT => {
+                let name = parse_string(operands_from(2)?);
+                let name_words = name.len() / 4 + 1;
+                module.entry_points.push(RawEntryPoint {
+                    execution_model: operand(0)?,
+                    interface: operands.get(2 + name_words..).unwrap_or_default().to_vec(),
+                    name,
+                });
+            },
+            op::TYPE_BOOL => {
+                module.types.insert(result_id()?, Type::Bool);
+            },
+            op::TYPE_INT => {
+                module.types.insert(result_id()?, Type::Int { width: operand(1)?, signed: operand(2)? != 0 });
+            },
+            op::TYPE_FLOAT => {
+                module.types.insert(result_id()?, Type::Float { width: operand(1)? });
+            },
+            op::TYPE_VECTOR => {
+                module.types.insert(result_id()?, Type::Vector { component: operand(1)?, count: operand(2)? });
+            },
+            op::TYPE_MATRIX => {
+                module.types.insert(result_id()?, Type::Matrix { column: operand(1)?, count: operand(2)? });
+            },
+            op::TYPE_IMAGE => {
+                module.types.insert(result_id()?, Type::Image { dim: operand(2)?, sampled: operand(6)? });
+            },
+            op::TYPE_SAMPLER => {
+                module.types.insert(result_id()?, Type::Sampler);
+            },
+            op::TYPE_SAMPLED_IMAGE => {
+                module.types.insert(result_id()?, Type::SampledImage);
+            },
+            op::TYPE_ARRAY => {
+                module.types.insert(result_id()?, Type::Array { element: operand(1)?, length: operand(2)? });
+            },
+            op::TYPE_RUNTIME_ARRAY => {
+                module.types.insert(result_id()?, Type::RuntimeArray { element: operand(1)? });
+            },
+            op::TYPE_STRUCT => {
+                module.types.insert(result_id()?, Type::Struct { members: operands_from(1)?.to_vec() });
+            },
+            op::TYPE_POINTER => {
+                module.types.insert(result_id()?, Type::Pointer { pointee: operand(2)? });
+            },
+            op::TYPE_ACCELERATION_STRUCTURE => {
+                module.types.insert(result_id()?, Type::AccelerationStructure);
+            },
+            // only the low word matters, it's only read for array lengths
+            op::CONSTANT => {
+                module.constants.insert(operand(1)?, operand(2)?);
+            },
+            op::VARIABLE => {
+                module.variables.push(Variable { ty: operand(0)?, id: operand(1)?, storage: operand(2)? });
+            },
+            op::DECORATE => {
+                module.decorations.insert((operand(0)?, operand(1)?), operand(2).unwrap_or(0));
+            },
+            op::MEMBER_DECORATE => {
+                module.member_decorations.insert((operand(0)?, operand(1)?, operand(2)?), operand(3).unwrap_or(0));
+            },
+            _ => {},
+        }
+
+        i += word_count;
+    }
+
+    Ok(module)
+}
+
+// literal strings are nul terminated and packed little endian into words
+fn parse_string(words: &[u32]) -> String {
+    let bytes = words
+        .iter()
+        .flat_map(|w| w.to_le_bytes())
+        .take_while(|b| *b != 0)
+        .collect::<Vec<_>>();
+
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+impl Module {
+    fn ty(&self, id: u32) -> Result<&Type> {
+        self.types.get(&id).ok_or_else(|| anyhow!("SPIR-V type {} is not declared", id))
+    }
+
+    fn pointee(&self, pointer: u32) -> Result<u32> {
+        match self.ty(pointer)? {
+            Type::Pointer { pointee } => Ok(*pointee),
+            _ => Err(anyhow!("SPIR-V variable type {} is not a pointer", pointer)),
+        }
+    }
+
+    fn name(&self, id: u32) -> Option<String> {
+        self.names.get(&id).filter(|n| !n.is_empty()).cloned()
+    }
+
+    fn array_length(&self, length: u32) -> Result<u32> {
+        self.constants
+            .get(&length)
+            .copied()
+            .ok_or_else(|| anyhow!("SPIR-V array length {} is not a constant", length))
+    }
+
+    fn entry_point(&self, raw: &RawEntryPoint) -> Result<EntryPoint> {
+        let stage = match raw.execution_model {
+            0 => vk::ShaderStageFlags::VERTEX,
+            1 => vk::ShaderStageFlags::TESSELLATION_CONTROL,
+            2 => vk::ShaderStageFlags::TESSELLATION_EVALUATION,
+            3 => vk::ShaderStageFlags::GEOMETRY,
+            4 => vk::ShaderStageFlags::FRAGMENT,
+            5 => vk::ShaderStageFlags::COMPUTE,
+            5364 => vk::ShaderStageFlags::TASK_EXT,
+            5365 => vk::ShaderStageFlags::MESH_EXT,
+            model => return Err(anyhow!("Entry point \"{}\" has unsupported execution model {}", raw.name, model)),
+        };
+
+        let mut inputs = vec![];
+        let mut outputs = vec![];
+
+        // from SPIR-V 1.4 the interface lists every global, not just inputs and outputs
+        for variable in self.variables.iter().filter(|v| raw.interface.contains(&v.id)) {
+            let list = match variable.storage {
+                storage::INPUT => &mut inputs,
+                storage::OUTPUT => &mut outputs,
+                _ => continue,
+            };
+
+            let Some(location) = self.decorations.get(&(variable.id, decoration::LOCATION)) else {
+                continue;
+            };
+
+            list.push(InterfaceVariable {
+                location: *location,
+                name: self.name(variable.id),
+                ty: self.interface_type(self.pointee(variable.ty)?, 0)?,
+            });
+        }
+
+        inputs.sort_by_key(|v| v.location);
+        outputs.sort_by_key(|v| v.location);
+
+        Ok(
+            EntryPoint {
+                name: raw.name.clone(),
+                stage,
+                inputs,
+                outputs,
+            }
+        )
+    }
+
+    fn interface_type(&self, id: u32, depth: u32) -> Result<InterfaceType> {
+        let depth = nested(id, depth)?;
+
+        Ok(match self.ty(id)? {
+            Type::Bool => InterfaceType { scalar: ScalarType::Bool, width: 32, components: 1, columns: 1, array_length: 1 },
+            Type::Int { width, signed } => InterfaceType {
+                scalar: if *signed { ScalarType::Int } else { ScalarType::Uint },
+                width: *width,
+                components: 1,
+                columns: 1,
+                array_length: 1,
+            },
+            Type::Float { width } => InterfaceType { scalar: ScalarType::Float, width: *width, components: 1, columns: 1, array_length: 1 },
+            Type::Vector { component, count } => InterfaceType { components: *count, ..self.interface_type(*component, depth)? },
+            Type::Matrix { column, count } => InterfaceType { columns: *count, ..self.interface_type(*column, depth)? },
+            Type::Array { element, length } => InterfaceType {
+                array_length: self.array_length(*length)?,
+                ..self.interface_type(*element, depth)?
+            },
+            ty => return Err(anyhow!("Unsupported type for a stage input or output: {:?}", ty)),
+        })
+    }
+
+    fn descriptor_binding(&self, variable: &Variable) -> Result<DescriptorBinding> {
+        let name = self.name(variable.id);
+        let describe = || name.clone().unwrap_or_else(|| format!("variable {}", variable.id));
+
+        let binding = *self.decorations
+            .get(&(variable.id, decoration::BINDING))
+            .ok_or_else(|| anyhow!("Descriptor {} has no binding decoration", describe()))?;
+        let set = self.decorations.get(&(variable.id, decoration::DESCRIPTOR_SET)).copied().unwrap_or(0);
+
+        let (element, count) = match self.ty(self.pointee(variable.ty)?)? {
+            Type::Array { element, length } => (*element, self.array_length(*length)?),
+            Type::RuntimeArray { element } => (*element, 0),
+            _ => (self.pointee(variable.ty)?, 1),
+        };
+
+        let descriptor_type = match (self.ty(element)?, variable.storage) {
+            (Type::Struct { .. }, storage::UNIFORM) if self.decorations.contains_key(&(element, decoration::BUFFER_BLOCK)) => {
+                vk::DescriptorType::STORAGE_BUFFER
+            },
+            (Type::Struct { .. }, storage::UNIFORM) if self.decorations.contains_key(&(element, decoration::BLOCK)) => {
+                vk::DescriptorType::UNIFORM_BUFFER
+            },
+            (Type::Struct { .. }, storage::STORAGE_BUFFER) => vk::DescriptorType::STORAGE_BUFFER,
+            (Type::Image { dim: DIM_BUFFER, sampled: 2 }, _) => vk::DescriptorType::STORAGE_TEXEL_BUFFER,
+            (Type::Image { dim: DIM_BUFFER, .. }, _) => vk::DescriptorType::UNIFORM_TEXEL_BUFFER,
+            (Type::Image { dim: DIM_SUBPASS_DATA, .. }, _) => vk::DescriptorType::INPUT_ATTACHMENT,
+            (Type::Image { sampled: 2, .. }, _) => vk::DescriptorType::STORAGE_IMAGE,
+            (Type::Image { .. }, _) => vk::DescriptorType::SAMPLED_IMAGE,
+            (Type::Sampler, _) => vk::DescriptorType::SAMPLER,
+            (Type::SampledImage, _) => vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            (Type::AccelerationStructure, _) => vk::DescriptorType::ACCELERATION_STRUCTURE_KHR,
+            (ty, storage) => return Err(anyhow!(
+                "Descriptor {} has type {:?} in storage class {}, which has no descriptor type",
+                describe(), ty, storage,
+            )),
+        };
+
+        Ok(
+            DescriptorBinding {
+                set,
+                binding,
+                descriptor_type,
+                count,
+                name,
+            }
+        )
+    }
+
+    // size in bytes of a type with explicit layout, as in a push constant block
+    fn size_of(&self, id: u32, depth: u32) -> Result<u32> {
+        let depth = nested(id, depth)?;
+        let overflow = || anyhow!("Size of SPIR-V type {} overflows", id);
+
+        Ok(match self.ty(id)? {
+            Type::Bool => 4,
+            Type::Int { width, .. } | Type::Float { width } => width / 8,
+            Type::Vector { component, count } => count.checked_mul(self.size_of(*component, depth)?).ok_or_else(overflow)?,
+            Type::Matrix { column, count } => count.checked_mul(self.size_of(*column, depth)?).ok_or_else(overflow)?,
+            Type::Array { element, length } => {
+                let stride = match self.decorations.get(&(id, decoration::ARRAY_STRIDE)) {
+                    Some(stride) => *stride,
+                    None => self.size_of(*element, depth)?,
+                };
+                self.array_length(*length)?.checked_mul(stride).ok_or_else(overflow)?
+            },
+            Type::RuntimeArray { .. } => 0,
+            Type::Struct { members } => {
+                let mut size = 0;
+                for (i, member) in members.iter().enumerate() {
+                    let i = i as u32;
+                    let offset = self.member_decorations.get(&(id, i, decoration::OFFSET)).copied().unwrap_or(size);
+
+                    // matrices in blocks are laid out by their stride, not their column size
+                    let member_size = match (self.ty(*member)?, self.member_decorations.get(&(id, i, decoration::MATRIX_STRIDE))) {
+                        (Type::Matrix { count, .. }, Some(stride)) => count.checked_mul(*stride).ok_or_else(overflow)?,
+                        _ => self.size_of(*member, depth)?,
+                    };
+
+                    size = size.max(offset.checked_add(member_size).ok_or_else(overflow)?);
+                }
+                size
+            },
+            ty => return Err(anyhow!("Type {:?} has no size in a block", ty)),
+        })
+    }
+}
+
+// one level deeper into the type id, failing once a type nests too deep to be anything but a cycle
+fn nested(id: u32, depth: u32) -> Result<u32> {
+    match depth < MAX_TYPE_DEPTH {
+        true => Ok(depth + 1),
+        false => Err(anyhow!("SPIR-V type {} nests more than {} deep", id, MAX_TYPE_DEPTH)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::base::buffer::{TexturedVertex, VertexLayout};
+    use crate::base::shader::{SCENE_FRAG, SCENE_VERT};
+    use crate::util::constants::*;
+
+    const VERSION_1_0: u32 = 0x0001_0000;
+
+    fn instruction(opcode: u32, operands: &[u32]) -> Vec<u32> {
+        let mut words = vec![((operands.len() as u32 + 1) << 16) | opcode];
+        words.extend_from_slice(operands);
+        words
+    }
+
+    fn module(instructions: &[Vec<u32>]) -> Vec<u32> {
+        let mut words = vec![MAGIC, VERSION_1_0, 0, 16, 0];
+        words.extend(instructions.iter().flatten());
+        words
+    }
+
+    fn scene_entry_point(shader: &crate::base::shader::ShaderFile) -> EntryPoint {
+        shader.embedded
+            .reflect()
+            .unwrap()
+            .entry_point("main", shader.stage)
+            .unwrap()
+            .clone()
+    }
+
+    fn locations(variables: &[InterfaceVariable]) -> Vec<(u32, String)> {
+        variables.iter().map(|v| (v.location, v.ty.to_string())).collect()
+    }
+
+    #[test]
+    fn header_is_checked() {
+        assert_eq!(check_header(&[MAGIC, VERSION_1_0, 0, 1, 0]).unwrap(), (1, 0));
+
+        let error = check_header(&[0xdead_beef, VERSION_1_0, 0, 1, 0]).unwrap_err();
+        assert!(error.to_string().contains("magic number"), "{}", error);
+
+        let error = check_header(&[MAGIC.swap_bytes(), VERSION_1_0, 0, 1, 0]).unwrap_err();
+        assert!(error.to_string().contains("byte-swapped"), "{}", error);
+
+        let error = check_header(&[MAGIC, VERSION_1_0, 0]).unwrap_err();
+        assert!(error.to_string().contains("too short"), "{}", error);
+
+        assert!(check_header(&[MAGIC, 0x0002_0000, 0, 1, 0]).is_err());
+        assert!(check_header(&[MAGIC, VERSION_1_0, 0, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn byte_swapped_modules_are_reflected() {
+        let words = SCENE_VERT.embedded.code().iter().map(|w| w.swap_bytes()).collect::<Vec<_>>();
+
+        assert_eq!(reflect(&words).unwrap(), SCENE_VERT.embedded.reflect().unwrap());
+    }
+
+    #[test]
+    fn scene_vertex_shader_is_reflected() {
+        let reflection = SCENE_VERT.embedded.reflect().unwrap();
+        let entry_point = scene_entry_point(&SCENE_VERT);
+
+        assert_eq!(
+            locations(&entry_point.inputs),
+            [(0, "vec2".to_string()), (1, "vec3".to_string()), (2, "vec2".to_string())],
+        );
+        assert_eq!(locations(&entry_point.outputs), [(0, "vec3".to_string()), (1, "vec2".to_string())]);
+
+        let bindings = reflection.descriptor_bindings
+            .iter()
+            .map(|b| (b.set, b.binding, b.descriptor_type, b.count))
+            .collect::<Vec<_>>();
+        assert_eq!(bindings, [(0, UNIFORM_BINDING, vk::DescriptorType::UNIFORM_BUFFER, 1)]);
+        assert_eq!(reflection.push_constant_size, None);
+    }
+
+    #[test]
+    fn scene_fragment_shader_is_reflected() {
+        let reflection = SCENE_FRAG.embedded.reflect().unwrap();
+        let entry_point = scene_entry_point(&SCENE_FRAG);
+
+        assert_eq!(locations(&entry_point.inputs), [(0, "vec3".to_string()), (1, "vec2".to_string())]);
+        assert_eq!(locations(&entry_point.outputs), [(0, "vec4".to_string())]);
+
+        let bindings = reflection.descriptor_bindings
+            .iter()
+            .map(|b| (b.set, b.binding, b.descriptor_type, b.count))
+            .collect::<Vec<_>>();
+        assert_eq!(bindings, [
+            (0, TEXTURE_BINDING, vk::DescriptorType::SAMPLED_IMAGE, 1),
+            (0, SAMPLER_BINDING, vk::DescriptorType::SAMPLER, 1),
+        ]);
+        assert_eq!(reflection.push_constant_size, None);
+    }
+
+    #[test]
+    fn push_constant_size_follows_member_offsets() {
+        let words = module(&[
+            instruction(op::TYPE_FLOAT, &[1, 32]),
+            instruction(op::TYPE_VECTOR, &[2, 1, 4]),
+            instruction(op::TYPE_STRUCT, &[3, 2, 1]),
+            instruction(op::MEMBER_DECORATE, &[3, 0, decoration::OFFSET, 0]),
+            instruction(op::MEMBER_DECORATE, &[3, 1, decoration::OFFSET, 16]),
+            instruction(op::TYPE_POINTER, &[4, storage::PUSH_CONSTANT, 3]),
+            instruction(op::VARIABLE, &[4, 5, storage::PUSH_CONSTANT]),
+        ]);
+
+        assert_eq!(reflect(&words).unwrap().push_constant_size, Some(20));
+    }
+
+    #[test]
+    fn vertex_input_mismatch_is_reported() {
+        let entry_point = scene_entry_point(&SCENE_VERT);
+        let mut attributes = VertexLayout::of::<TexturedVertex>().attributes;
+
+        entry_point.check_vertex_input(&attributes).unwrap();
+
+        let mut wrong_format = attributes.clone();
+        wrong_format.iter_mut().find(|a| a.location == 1).unwrap().format = vk::Format::R32G32_SFLOAT;
+        let error = entry_point.check_vertex_input(&wrong_format).unwrap_err();
+        assert!(error.to_string().contains("location 1"), "{}", error);
+
+        attributes.retain(|a| a.location != 2);
+        let error = entry_point.check_vertex_input(&attributes).unwrap_err();
+        assert!(error.to_string().contains("has no vertex attribute"), "{}", error);
+    }
+
+    #[test]
+    fn stage_mismatch_is_reported() {
+        let vertex = scene_entry_point(&SCENE_VERT);
+        let fragment = scene_entry_point(&SCENE_FRAG);
+
+        fragment.check_inputs_from(&vertex).unwrap();
+
+        let mut narrower = vertex.clone();
+        narrower.outputs[0].ty.components = 2;
+        let error = fragment.check_inputs_from(&narrower).unwrap_err();
+        assert!(error.to_string().contains("location 0"), "{}", error);
+
+        let mut missing = vertex.clone();
+        missing.outputs.retain(|o| o.location != 1);
+        let error = fragment.check_inputs_from(&missing).unwrap_err();
+        assert!(error.to_string().contains("doesn't write"), "{}", error);
+    }
+
+    #[test]
+    fn truncated_instructions_are_rejected() {
+        // says it is 4 words long, but the module ends after 2
+        let mut words = module(&[instruction(op::TYPE_FLOAT, &[1, 32])]);
+        words.pop();
+        words[HEADER_WORDS] = (4 << 16) | op::TYPE_FLOAT;
+        let error = reflect(&words).unwrap_err();
+        assert!(error.to_string().contains("invalid length"), "{}", error);
+
+        let words = module(&[vec![op::TYPE_FLOAT]]);
+        assert!(reflect(&words).is_err());
+    }
+
+    #[test]
+    fn short_string_operands_are_rejected() {
+        let error = reflect(&module(&[instruction(op::ENTRY_POINT, &[0])])).unwrap_err();
+        assert!(error.to_string().contains("missing operands"), "{}", error);
+
+        assert!(reflect(&module(&[instruction(op::NAME, &[])])).is_err());
+    }
+
+    #[test]
+    fn self_referential_types_are_rejected() {
+        let words = module(&[
+            instruction(op::TYPE_STRUCT, &[1, 1]),
+            instruction(op::TYPE_POINTER, &[2, storage::PUSH_CONSTANT, 1]),
+            instruction(op::VARIABLE, &[2, 3, storage::PUSH_CONSTANT]),
+        ]);
+
+        let error = reflect(&words).unwrap_err();
+        assert!(error.to_string().contains("nests"), "{}", error);
+    }
+
+    #[test]
+    fn oversized_arrays_are_rejected() {
+        let words = module(&[
+            instruction(op::TYPE_INT, &[1, 32, 0]),
+            instruction(op::CONSTANT, &[1, 2, u32::MAX]),
+            instruction(op::TYPE_FLOAT, &[3, 32]),
+            instruction(op::TYPE_ARRAY, &[4, 3, 2]),
+            instruction(op::TYPE_POINTER, &[5, storage::PUSH_CONSTANT, 4]),
+            instruction(op::VARIABLE, &[5, 6, storage::PUSH_CONSTANT]),
+        ]);
+
+        let error = reflect(&words).unwrap_err();
+        assert!(error.to_string().contains("overflows"), "{}", error);
+    }
+}
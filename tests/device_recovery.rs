@@ -0,0 +1,161 @@
+use std::{cell::Cell, rc::Rc};
+
+use vulkan_testing::base::{config::Config, device::DeviceSelector, record, texture::Texture, App};
+
+use anyhow::Result;
+
+const WIDTH: u32 = 64;
+const HEIGHT: u32 = 64;
+
+/*
+ * Tests
+ */
+
+// a real device loss can't be caused on demand, so this goes through the same rebuild directly
+#[test]
+fn recreated_device_renders_the_same_frame() {
+    let mut app = create_app().unwrap();
+
+    let before = unsafe { app.capture_frame() }.unwrap();
+
+    let resets = Rc::new(Cell::new(0));
+    let counted = resets.clone();
+    app.set_device_reset_hook(move |app| {
+        counted.set(counted.get() + 1);
+        app.set_recorder(record::record_default);
+        Ok(())
+    });
+    app.set_recorder(record::record_default);
+
+    unsafe { app.recreate_device() }.unwrap();
+
+    assert_eq!(resets.get(), 1);
+    assert!(!app.device_lost);
+    assert!(app.recorder.is_some(), "the hook's recorder was not kept");
+    assert_eq!(app.frame, 0);
+
+    let after = unsafe { app.capture_frame() }.unwrap();
+
+    assert_eq!((before.width, before.height), (after.width, after.height));
+    assert!(before.pixels == after.pixels, "frame changed after recreating the device");
+}
+
+#[test]
+fn recreating_drops_the_recorder() {
+    let mut app = create_app().unwrap();
+    app.set_recorder(record::record_default);
+
+    unsafe { app.recreate_device() }.unwrap();
+
+    assert!(app.recorder.is_none());
+    unsafe { app.render_frame() }.unwrap();
+}
+
+#[test]
+fn lost_device_is_recovered_while_rendering() {
+    let mut app = create_app().unwrap();
+    let resets = install_scene(&mut app);
+
+    let before = unsafe { app.capture_frame() }.unwrap();
+
+    app.simulate_device_loss();
+    unsafe { app.render_frame() }.unwrap();
+
+    assert_eq!(resets.get(), 1);
+    assert!(!app.device_lost);
+    assert!(app.recorder.is_some(), "the hook's recorder was not installed");
+    assert_eq!(app.texture.extent.width, 2, "the hook's texture was not installed");
+
+    unsafe { app.render_frame() }.unwrap();
+    let after = unsafe { app.capture_frame() }.unwrap();
+
+    assert!(before.pixels == after.pixels, "frame changed after recovering the device");
+}
+
+// there is no frame to return, but the next capture has one
+#[test]
+fn lost_device_is_recovered_while_capturing() {
+    let mut app = create_app().unwrap();
+    let resets = install_scene(&mut app);
+
+    let before = unsafe { app.capture_frame() }.unwrap();
+
+    app.simulate_device_loss();
+    let error = unsafe { app.capture_frame() }.unwrap_err();
+
+    assert!(error.is_device_lost(), "unexpected error {}", error);
+    assert_eq!(resets.get(), 1);
+    assert!(!app.device_lost);
+    assert_eq!(app.texture.extent.width, 2, "the hook's texture was not installed");
+
+    let after = unsafe { app.capture_frame() }.unwrap();
+
+    assert!(before.pixels == after.pixels, "frame changed after recovering the device");
+}
+
+// a rebuild that fails leaves nothing to render with, so everything reports the loss until one works
+#[test]
+fn failed_rebuild_is_retried() {
+    let mut app = create_app().unwrap();
+    let resets = install_scene(&mut app);
+
+    app.config.device = Some(DeviceSelector::parse("no such device").unwrap());
+    app.simulate_device_loss();
+
+    assert!(unsafe { app.render_frame() }.is_err());
+    assert!(app.device_lost);
+    assert_eq!(resets.get(), 0);
+
+    assert!(unsafe { app.render_frame() }.is_err());
+    assert!(unsafe { app.capture_frame() }.is_err());
+    assert!(unsafe { app.set_msaa_samples(4) }.is_err_and(|e| e.is_device_lost()));
+    assert!(unsafe { app.create_index_buffer(&[0u16, 1, 2]) }.is_err_and(|e| e.is_device_lost()));
+
+    app.config.device = None;
+    unsafe { app.render_frame() }.unwrap();
+
+    assert!(!app.device_lost);
+    assert_eq!(resets.get(), 1);
+    unsafe { app.capture_frame() }.unwrap();
+}
+
+/*
+ * Harness
+ */
+
+fn create_app() -> Result<App> {
+    let config = Config {
+        pipeline_cache: None,
+        ..Default::default()
+    };
+
+    Ok(App::create_headless(WIDTH, HEIGHT, config)?)
+}
+
+// a 2x2 checkerboard and the default recorder, set now and again on every device reset. returns
+// how many resets there have been
+fn install_scene(app: &mut App) -> Rc<Cell<u32>> {
+    fn set_scene(app: &mut App) -> Result<()> {
+        let pixels = [[255, 0, 0, 255], [0, 0, 255, 255], [0, 0, 255, 255], [255, 0, 0, 255]].concat();
+
+        unsafe {
+            let texture = Texture::from_rgba8(&app.logical_device, &mut app.allocator, app.queue_data.graphics, *app.command_pool, 2, 2, &pixels)?;
+            app.set_texture(texture)?;
+        }
+
+        app.set_recorder(record::record_default);
+
+        Ok(())
+    }
+
+    set_scene(app).unwrap();
+
+    let resets = Rc::new(Cell::new(0));
+    let counted = resets.clone();
+    app.set_device_reset_hook(move |app| {
+        counted.set(counted.get() + 1);
+        set_scene(app)
+    });
+
+    resets
+}
@@ -0,0 +1,71 @@
+use std::{cell::RefCell, rc::Rc};
+
+use vulkan_testing::{
+    base::{config::Config, record, App},
+    util::constants::*,
+};
+
+use anyhow::Result;
+
+const WIDTH: u32 = 64;
+const HEIGHT: u32 = 64;
+
+// enough to go round the biggest ring several times
+const FRAMES: usize = 4 * MAX_FRAMES_IN_FLIGHT + 1;
+
+/*
+ * Tests
+ */
+
+#[test]
+fn every_slot_is_used_in_order() {
+    for frames_in_flight in 1..=MAX_FRAMES_IN_FLIGHT {
+        let slots = render_slots(frames_in_flight, FRAMES).unwrap();
+
+        let expected = (0..FRAMES).map(|i| i % frames_in_flight).collect::<Vec<_>>();
+        assert_eq!(slots, expected, "with {} frames in flight", frames_in_flight);
+    }
+}
+
+#[test]
+fn out_of_range_frames_in_flight_is_rejected() {
+    for frames_in_flight in [0, MAX_FRAMES_IN_FLIGHT + 1] {
+        let config = Config {
+            pipeline_cache: None,
+            frames_in_flight,
+            ..Default::default()
+        };
+
+        assert!(App::create_headless(WIDTH, HEIGHT, config).is_err(), "{} frames in flight was accepted", frames_in_flight);
+    }
+}
+
+/*
+ * Harness
+ */
+
+// renders headless frames, returning the frame slot each one was recorded with
+fn render_slots(frames_in_flight: usize, frames: usize) -> Result<Vec<usize>> {
+    let config = Config {
+        pipeline_cache: None,
+        frames_in_flight,
+        ..Default::default()
+    };
+
+    let mut app = App::create_headless(WIDTH, HEIGHT, config)?;
+
+    let slots = Rc::new(RefCell::new(vec![]));
+    let recorded = slots.clone();
+    app.set_recorder(move |context| {
+        recorded.borrow_mut().push(context.frame);
+        record::record_default(context)
+    });
+
+    for _ in 0..frames {
+        unsafe { app.render_frame()? };
+    }
+
+    drop(app);
+
+    Ok(Rc::try_unwrap(slots).unwrap().into_inner())
+}